@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::result;
+
+use ast::{Statement, Type, Value};
+
+// The signature of a Rust function that can be called from pal. It's given the values of its
+// arguments, which have already been checked against its parameter types, and returns either its
+// value (or `None` if it returns `void`) or a message describing why it failed.
+pub type NativeFn = Fn(Vec<Value>) -> result::Result<Option<Value>, String> + Send + Sync;
+
+// A function, either defined by name at the top level of a program, created by an anonymous
+// function expression or registered from Rust.
+pub struct Function {
+    // `None` for anonymous functions.
+    pub name: Option<String>,
+    pub return_type: Type,
+    pub kind: FunctionKind,
+}
+
+pub enum FunctionKind {
+    // A function written in pal.
+    Pal {
+        params: Vec<String>,
+        body: Vec<Statement>,
+        // The local variables that were visible where an anonymous function was created. They're
+        // copied into the function, so assigning to them inside it doesn't change the originals.
+        captured: HashMap<String, Value>,
+    },
+    // A function written in Rust, with the type of each of its parameters.
+    Native {
+        params: Vec<Type>,
+        func: Box<NativeFn>,
+    },
+    // One of the functions built into the language, like `map`, used as a value. Builtins check their
+    // own arguments, since some of them take a varying number.
+    Builtin,
+}
+
+impl Function {
+    pub fn new(name: Option<&str>, return_type: &Type, params: &[String], body: &[Statement],
+               captured: HashMap<String, Value>) -> Self {
+        Function {
+            name: name.map(String::from),
+            return_type: return_type.clone(),
+            kind: FunctionKind::Pal { params: Vec::from(params), body: Vec::from(body), captured: captured },
+        }
+    }
+
+    pub fn native(name: &str, params: &[Type], return_type: &Type, func: Box<NativeFn>) -> Self {
+        Function {
+            name: Some(String::from(name)),
+            return_type: return_type.clone(),
+            kind: FunctionKind::Native { params: Vec::from(params), func: func },
+        }
+    }
+
+    pub fn builtin(name: &str) -> Self {
+        Function { name: Some(String::from(name)), return_type: Type::Any, kind: FunctionKind::Builtin }
+    }
+
+    // Returns the number of arguments the function takes, or `None` for a builtin, which checks its
+    // own arguments.
+    pub fn arity(&self) -> Option<usize> {
+        match self.kind {
+            FunctionKind::Pal { ref params, .. } => Some(params.len()),
+            FunctionKind::Native { ref params, .. } => Some(params.len()),
+            FunctionKind::Builtin => None,
+        }
+    }
+
+    // Describes the function for error messages, e.g. "the function `f`".
+    pub fn description(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("the function `{}`", name),
+            None => String::from("the anonymous function"),
+        }
+    }
+}
+
+// Functions are only equal to themselves; comparing their code wouldn't tell whether they behave
+// the same.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self as *const Function == other as *const Function
+    }
+}
+
+// Native functions can't be inspected, so functions are shown by their name and signature.
+impl fmt::Debug for Function {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FunctionKind::Pal { ref params, .. } =>
+                write!(fmt, "{} {:?}({})", self.return_type, self.name, params.join(", ")),
+            FunctionKind::Native { ref params, .. } => {
+                let types: Vec<_> = params.iter().map(|t| format!("{}", t)).collect();
+                write!(fmt, "{} {:?}({}) <native>", self.return_type, self.name, types.join(", "))
+            }
+            FunctionKind::Builtin => write!(fmt, "{:?} <builtin>", self.name),
+        }
+    }
+}
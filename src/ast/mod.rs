@@ -1,13 +1,28 @@
 #[cfg(test)]
 mod test;
 
+mod function;
+mod span;
+
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::Arc;
+
+use num::BigInt;
+
+pub use self::function::{Function, FunctionKind, NativeFn};
+pub use self::span::Span;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type {
+    // Any type of value; there's no keyword for it, but native functions can take it as a parameter.
+    Any,
     Array,
     Bool,
+    Float,
+    Function,
     Int,
+    Map,
     Str,
     Void,
 }
@@ -16,9 +31,13 @@ impl Type {
     pub fn as_string_with_article(&self) -> &str {
         // Returns the name of the type with the correct English indefinite article prepended.
         match *self {
+            Type::Any => "any value",
             Type::Array => "an array",
             Type::Bool => "a boolean",
+            Type::Float => "a float",
+            Type::Function => "a function",
             Type::Int => "an int",
+            Type::Map => "a map",
             Type::Str => "a string",
             Type::Void => "nothing",
         }
@@ -28,9 +47,13 @@ impl Type {
 impl fmt::Display for Type {
     fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Type::Any => write!(fmt, "any"),
             Type::Array => write!(fmt, "array"),
             Type::Bool => write!(fmt, "boolean"),
+            Type::Float => write!(fmt, "float"),
+            Type::Function => write!(fmt, "function"),
             Type::Int => write!(fmt, "int"),
+            Type::Map => write!(fmt, "dictionary"),
             Type::Str => write!(fmt, "string"),
             Type::Void => write!(fmt, "void"),
         }
@@ -38,11 +61,17 @@ impl fmt::Display for Type {
 }
 
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Array(Vec<Value>),
+    // An int too big to fit in an `i64`; only produced when big ints are enabled.
+    BigInt(BigInt),
     Bool(bool),
+    Float(f64),
+    Function(Arc<Function>),
     Int(i64),
+    // Maps from string keys to values, kept in order of their keys.
+    Map(BTreeMap<String, Value>),
     Str(String),
 }
 
@@ -52,7 +81,11 @@ impl Value {
         match *self {
             Value::Array(_) => "an array",
             Value::Bool(_) => "a boolean",
+            Value::BigInt(_) => "an int",
+            Value::Float(_) => "a float",
+            Value::Function(_) => "a function",
             Value::Int(_) => "an int",
+            Value::Map(_) => "a map",
             Value::Str(_) => "a string",
         }
     }
@@ -60,9 +93,14 @@ impl Value {
     // Checks whether the value is of a certain type.
     pub fn is_a(&self, t: &Type) -> bool {
         match (self, t) {
+            (_, &Type::Any) |
             (&Value::Array(_), &Type::Array) |
             (&Value::Bool(_), &Type::Bool) |
+            (&Value::Float(_), &Type::Float) |
+            (&Value::Function(_), &Type::Function) |
+            (&Value::BigInt(_), &Type::Int) |
             (&Value::Int(_), &Type::Int) |
+            (&Value::Map(_), &Type::Map) |
             (&Value::Str(_), &Type::Str) => true,
             _ => false
         }
@@ -85,8 +123,28 @@ impl fmt::Display for Value {
 
                 write!(fmt, "]")
             }
+            Value::BigInt(ref b) => write!(fmt, "{}", b),
             Value::Bool(b) => write!(fmt, "{}", b),
+            // Use the debug formatting so that whole numbers are written as `1.0` rather than `1`.
+            Value::Float(f) => write!(fmt, "{:?}", f),
+            Value::Function(ref func) => match func.name {
+                Some(ref name) => write!(fmt, "<function {}>", name),
+                None => write!(fmt, "<function>"),
+            },
             Value::Int(i) => write!(fmt, "{}", i),
+            Value::Map(ref map) => {
+                try!(write!(fmt, "{{"));
+
+                for (i, (key, val)) in map.iter().enumerate() {
+                    if i != 0 {
+                        try!(write!(fmt, ", "));
+                    }
+
+                    try!(write!(fmt, "\"{}\": {}", key, val));
+                }
+
+                write!(fmt, "}}")
+            }
             Value::Str(ref s) => write!(fmt, "{}", s),
         }
     }
@@ -167,26 +225,48 @@ impl fmt::Display for BinOp {
 
 
 #[derive(Clone, Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
     Array(Vec<Expr>),
     ArrayElement(String, Box<Expr>, Vec<Expr>),
     BinExp(Box<Expr>, BinOp, Box<Expr>),
-    Call(String, Vec<Expr>),
+    // A call of the function that the first expression evaluates to.
+    Call(Box<Expr>, Vec<Expr>),
+    // An interpolated string like `"a ${b} c"`, which is the concatenation of its parts once they've
+    // been converted to strings.
+    Interpolation(Vec<Expr>),
+    Keys(Box<Expr>),
+    // An anonymous function with its return type, parameters and body.
+    Lambda(Type, Vec<String>, Vec<Statement>),
     Length(Box<Expr>),
     Letters(Box<Expr>),
+    // A map literal, with the key and value expressions of each entry.
+    Map(Vec<(Expr, Expr)>),
     Not(Box<Expr>),
     Range(Box<Expr>, Box<Expr>),
     ReadLine,
     Step(Box<Expr>, Box<Expr>, Box<Expr>),
+    ToFloat(Box<Expr>),
+    ToInt(Box<Expr>),
     Value(Value),
+    Values(Box<Expr>),
     Var(String),
 }
 
 impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind: kind, span: span }
+    }
+
     // Returns the precedence level of the expression.
     fn precedence(&self) -> Precedence {
-        match *self {
-            Expr::BinExp(_, ref o, _) => o.precedence(),
+        match self.kind {
+            ExprKind::BinExp(_, ref o, _) => o.precedence(),
             _ => Precedence::Constant,
         }
     }
@@ -194,8 +274,8 @@ impl Expr {
 
 impl fmt::Display for Expr {
     fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Expr::Array(ref vec) => {
+        match self.kind {
+            ExprKind::Array(ref vec) => {
                 try!(write!(fmt, "["));
 
                 for (i, val) in vec.iter().enumerate() {
@@ -208,7 +288,7 @@ impl fmt::Display for Expr {
 
                 write!(fmt, "]")
             }
-            Expr::ArrayElement(ref var, ref index, ref indexes) => {
+            ExprKind::ArrayElement(ref var, ref index, ref indexes) => {
                 try!(write!(fmt, "{}[{}]", var, index));
 
                 for i in indexes.iter() {
@@ -217,7 +297,7 @@ impl fmt::Display for Expr {
 
                 Ok(())
             }
-            Expr::BinExp(ref exp1, ref op, ref exp2) => {
+            ExprKind::BinExp(ref exp1, ref op, ref exp2) => {
                 let op_precendence = op.precedence();
 
                 // Wrap the left-hand side in parentheses if its precedence is lower than the operator
@@ -236,8 +316,13 @@ impl fmt::Display for Expr {
                     write!(fmt, "{}", exp2)
                 }
             }
-            Expr::Call(ref func, ref args) => {
-                try!(write!(fmt, "{}(", func));
+            ExprKind::Call(ref func, ref args) => {
+                // Wrap the function in parentheses unless it's a single term
+                if func.precedence() == Precedence::Constant {
+                    try!(write!(fmt, "{}(", func));
+                } else {
+                    try!(write!(fmt, "({})(", func));
+                }
 
                 // Write the arguments, separated by commas
                 for (i, arg) in args.iter().enumerate() {
@@ -250,42 +335,99 @@ impl fmt::Display for Expr {
 
                 write!(fmt, ")")
             }
-            Expr::Length(ref exp) => write!(fmt, "length({})", exp),
-            Expr::Letters(ref exp) => write!(fmt, "letters({})", exp),
-            Expr::Not(ref exp) => write!(fmt, "!{}", exp),
-            Expr::Range(ref start, ref end) => write!(fmt, "range({}, {})", start, end),
-            Expr::ReadLine => write!(fmt, "readline()"),
-            Expr::Step(ref start, ref end, ref step) => write!(fmt, "step({}, {}, {})", start, end, step),
-            Expr::Value(ref val) => write!(fmt, "{}", val),
-            Expr::Var(ref var) => write!(fmt, "{}", var),
+            ExprKind::Interpolation(ref parts) => {
+                try!(write!(fmt, "\""));
+
+                // Write the string literal parts as-is and wrap everything else in `${...}`
+                for part in parts.iter() {
+                    match part.kind {
+                        ExprKind::Value(Value::Str(ref s)) => try!(write!(fmt, "{}", s)),
+                        _ => try!(write!(fmt, "${{{}}}", part)),
+                    }
+                }
+
+                write!(fmt, "\"")
+            }
+            ExprKind::Keys(ref exp) => write!(fmt, "keys({})", exp),
+            ExprKind::Lambda(ref return_type, ref params, ref body) => {
+                try!(write!(fmt, "{} ({}) {{", return_type, params.join(", ")));
+
+                // Write the body on one line
+                for stmt in body.iter() {
+                    try!(write!(fmt, " {}", format!("{}", stmt).trim()));
+                }
+
+                write!(fmt, " }}")
+            }
+            ExprKind::Length(ref exp) => write!(fmt, "length({})", exp),
+            ExprKind::Letters(ref exp) => write!(fmt, "letters({})", exp),
+            ExprKind::Map(ref entries) => {
+                try!(write!(fmt, "{{"));
+
+                for (i, &(ref key, ref val)) in entries.iter().enumerate() {
+                    if i != 0 {
+                        try!(write!(fmt, ", "));
+                    }
+
+                    try!(write!(fmt, "{}: {}", key, val));
+                }
+
+                write!(fmt, "}}")
+            }
+            ExprKind::Not(ref exp) => write!(fmt, "!{}", exp),
+            ExprKind::Range(ref start, ref end) => write!(fmt, "range({}, {})", start, end),
+            ExprKind::ReadLine => write!(fmt, "readline()"),
+            ExprKind::Step(ref start, ref end, ref step) => write!(fmt, "step({}, {}, {})", start, end, step),
+            ExprKind::ToFloat(ref exp) => write!(fmt, "to_float({})", exp),
+            ExprKind::ToInt(ref exp) => write!(fmt, "to_int({})", exp),
+            ExprKind::Value(ref val) => write!(fmt, "{}", val),
+            ExprKind::Values(ref exp) => write!(fmt, "values({})", exp),
+            ExprKind::Var(ref var) => write!(fmt, "{}", var),
         }
     }
 }
 
 #[derive(Clone, Debug)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum StmtKind {
     ArrayElemAssign(String, Expr, Vec<Expr>, Expr),
-    For(String, Expr, Vec<Statement>),
+    // Leaves the innermost loop, or the loop with the given label.
+    Break(Option<String>),
+    // Skips to the next iteration of the innermost loop, or the loop with the given label.
+    Continue(Option<String>),
+    For(Option<String>, String, Expr, Vec<Statement>),
     Defun(Type, String, Vec<String>, Vec<Statement>),
     Delete(String, Expr, Vec<Expr>),
+    // A statement that couldn't be parsed; only present in the partial AST of a program with syntax
+    // errors.
+    Error,
     If(Expr, Vec<Statement>, Vec<Statement>),
     Let(String, Expr),
     Print(Expr),
     PrintLine(Expr),
     Return(Expr),
-    VoidCall(String, Vec<Expr>),
-    While(Expr, Vec<Statement>),
+    VoidCall(Expr, Vec<Expr>),
+    While(Option<String>, Expr, Vec<Statement>),
     VarAssign(String, Expr),
 }
 
 impl Statement {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Statement { kind: kind, span: span }
+    }
+
     // Formats the expression with indentation before it.
     fn fmt_with_indent(&self, mut fmt: &mut fmt::Formatter, indent_level: u32) -> fmt::Result {
         // Creates a new string that is as many spaces as `indent level * 4`.
         let indentation : String = (0..indent_level * 4).map(|_| " ").collect();
 
-        match *self {
-            Statement::ArrayElemAssign(ref var, ref index, ref indexes, ref exp) => {
+        match self.kind {
+            StmtKind::ArrayElemAssign(ref var, ref index, ref indexes, ref exp) => {
                 try!(write!(fmt, "{}{}[{}]", indentation, var, index));
 
                 for i in indexes.iter() {
@@ -294,7 +436,11 @@ impl Statement {
 
                 writeln!(fmt, " = {};", exp)
             }
-            Statement::Defun(ref return_type, ref name, ref params, ref body) => {
+            StmtKind::Break(None) => writeln!(fmt, "{}break;", indentation),
+            StmtKind::Break(Some(ref label)) => writeln!(fmt, "{}break {};", indentation, label),
+            StmtKind::Continue(None) => writeln!(fmt, "{}continue;", indentation),
+            StmtKind::Continue(Some(ref label)) => writeln!(fmt, "{}continue {};", indentation, label),
+            StmtKind::Defun(ref return_type, ref name, ref params, ref body) => {
                 try!(write!(fmt, "{}{} {}(", indentation, return_type, name));
 
                 // Write the parameters, separated by commas
@@ -315,7 +461,7 @@ impl Statement {
 
                 writeln!(fmt, "{}}}", indentation)
             }
-            Statement::Delete(ref var, ref index, ref indexes) => {
+            StmtKind::Delete(ref var, ref index, ref indexes) => {
                 try!(write!(fmt, "{}delete {}[{}]", indentation, var, index));
 
                 for i in indexes.iter() {
@@ -324,8 +470,15 @@ impl Statement {
 
                 Ok(())
             }
-            Statement::For(ref var, ref exp, ref block) => {
-                try!(writeln!(fmt, "{}for {} in {} {{", indentation, var, exp));
+            StmtKind::Error => writeln!(fmt, "{}<error>;", indentation),
+            StmtKind::For(ref label, ref var, ref exp, ref block) => {
+                try!(write!(fmt, "{}", indentation));
+
+                if let Some(ref label) = *label {
+                    try!(write!(fmt, "{}: ", label));
+                }
+
+                try!(writeln!(fmt, "for {} in {} {{", var, exp));
 
                 for stmt in block.iter() {
                     try!(stmt.fmt_with_indent(fmt, indent_level + 1));
@@ -333,7 +486,7 @@ impl Statement {
 
                 writeln!(fmt, "{}}}", indentation)
             }
-            Statement::If(ref clause, ref true_block, ref false_block) => {
+            StmtKind::If(ref clause, ref true_block, ref false_block) => {
                 try!(writeln!(fmt, "{}if ({}) {{", indentation, clause));
 
                 // Write the block statements with one more level of indentation
@@ -355,13 +508,17 @@ impl Statement {
 
                 writeln!(fmt, "{}}}", indentation)
             }
-            Statement::Let(ref var, ref exp) => writeln!(fmt, "{}let {} = {};", indentation, var, exp),
-            Statement::Print(ref exp) => writeln!(fmt, "{}print {};", indentation, exp),
-            Statement::PrintLine(ref exp) => writeln!(fmt, "{}print_line {};", indentation, exp),
-            Statement::Return(ref exp) => writeln!(fmt, "{}return {};", indentation, exp),
-            Statement::VarAssign(ref var, ref exp) => writeln!(fmt, "{}{} = {};", indentation, var, exp),
-            Statement::VoidCall(ref name, ref args) => {
-                try!(write!(fmt, "{}{}(", indentation, name));
+            StmtKind::Let(ref var, ref exp) => writeln!(fmt, "{}let {} = {};", indentation, var, exp),
+            StmtKind::Print(ref exp) => writeln!(fmt, "{}print {};", indentation, exp),
+            StmtKind::PrintLine(ref exp) => writeln!(fmt, "{}print_line {};", indentation, exp),
+            StmtKind::Return(ref exp) => writeln!(fmt, "{}return {};", indentation, exp),
+            StmtKind::VarAssign(ref var, ref exp) => writeln!(fmt, "{}{} = {};", indentation, var, exp),
+            StmtKind::VoidCall(ref func, ref args) => {
+                if func.precedence() == Precedence::Constant {
+                    try!(write!(fmt, "{}{}(", indentation, func));
+                } else {
+                    try!(write!(fmt, "{}({})(", indentation, func));
+                }
 
                 // Write the arguments, separated by commas
                 for (i, arg) in args.iter().enumerate() {
@@ -374,8 +531,14 @@ impl Statement {
 
                 write!(fmt, ");")
             }
-            Statement::While(ref clause, ref block) => {
-                try!(writeln!(fmt, "{}while ({}) {{", indentation, clause));
+            StmtKind::While(ref label, ref clause, ref block) => {
+                try!(write!(fmt, "{}", indentation));
+
+                if let Some(ref label) = *label {
+                    try!(write!(fmt, "{}: ", label));
+                }
+
+                try!(writeln!(fmt, "while ({}) {{", clause));
 
                 // Write the block statements with one more level of indentation
                 for stmt in block.iter() {
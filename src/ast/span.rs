@@ -0,0 +1,31 @@
+use std::cmp;
+
+// The byte offsets in the source text that a node of the AST was parsed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn new(lo: usize, hi: usize) -> Self {
+        Span { lo: lo, hi: hi }
+    }
+
+    // Returns the (one-indexed) line and column that the span starts at in the given text.
+    pub fn line_col(&self, text: &str) -> (usize, usize) {
+        let mut lo = cmp::min(self.lo, text.len());
+
+        // Back up to the nearest character boundary in case the span doesn't line up with the text.
+        while !text.is_char_boundary(lo) {
+            lo -= 1;
+        }
+
+        let prefix = &text[..lo];
+        let line = prefix.matches('\n').count() + 1;
+        let line_start = prefix.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = prefix[line_start..].chars().count() + 1;
+
+        (line, column)
+    }
+}
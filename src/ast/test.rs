@@ -75,3 +75,12 @@ fn display_complex_function() {
     let string = "void range(i) {\n    while (i >= 0) {\n        total = total + sum3(i, i + 1, i + 2);\n        i = i - 1;\n    }\n}\n";
     assert_eq!(string, format!("{}", stmt));
 }
+
+#[test]
+fn span_line_col() {
+    let text = "let x = 1;\nlet y = x + true;\n";
+
+    assert_eq!((1, 1), Span::new(0, 3).line_col(text));
+    assert_eq!((2, 1), Span::new(11, 14).line_col(text));
+    assert_eq!((2, 9), Span::new(19, 27).line_col(text));
+}
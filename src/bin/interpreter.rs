@@ -1,14 +1,29 @@
 extern crate pal;
 
 use std::env;
-use pal::{repl, run_file};
+use std::io::{self, Write};
+use std::process;
+use pal::{repl, run_file_with_config, Config};
 
 fn main() {
-    let mut args = env::args();
-    let _ = args.next();
+    let mut config = Config::default();
+    let mut file = None;
 
-    match args.next() {
-        Some(arg) => run_file(&arg).unwrap(),
-        None => repl(),
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--big-ints" => config.big_ints = true,
+            _ => file = Some(arg),
+        }
+    }
+
+    match file {
+        // `run_file` has already reported the error, so just exit with a failure status.
+        Some(file) => if run_file_with_config(&file, config).is_err() {
+            process::exit(1);
+        },
+        None => if let Err(e) = repl() {
+            writeln!(io::stderr(), "{}", e).unwrap();
+            process::exit(1);
+        },
     }
 }
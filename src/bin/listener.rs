@@ -1,29 +1,24 @@
 extern crate pal;
 
-use std::io::{self, Read, Write};
-use std::net::TcpListener;
+use std::env;
+use std::io::{self, Write};
+use std::process;
 
-fn main() {
-    let mut stderr = io::stderr();
-    let mut string = String::new();
-
-    loop {
-        let listener = TcpListener::bind("localhost:7777").expect("Unable to open socket");
-        let (mut stream, _) = listener.accept().expect("Unable to accept connection");
-
-        if let Err(e) = stream.read_to_string(&mut string) {
-            writeln!(stderr, "Error reading string from source: {}", e).unwrap();
-            continue;
-        }
+use pal::{Config, Limits};
 
-        if string.is_empty() {
-            continue;
-        }
-
-        if let Err(e) = pal::run_program(&string) {
-            writeln!(stderr, "{}", e).unwrap();
+// Serves pal programs over TCP. Each client sends a program as line-delimited JSON and gets the
+// program's output back over the same connection; see `pal::Request` and `pal::event_to_json`.
+//
+//     listener [--host HOST] [--port PORT]
+fn main() {
+    let listener = match pal::listen_from_args("listener", env::args().skip(1), 7777) {
+        Ok(listener) => listener,
+        Err(message) => {
+            writeln!(io::stderr(), "{}", message).unwrap();
+            process::exit(1);
         }
+    };
 
-        string.clear();
-    }
+    let config = Config { limits: Limits::for_server(), ..Config::default() };
+    pal::serve_tcp(listener, config);
 }
@@ -0,0 +1,18 @@
+extern crate pal;
+
+use std::io::{self, Write};
+use std::process;
+
+// Serves the Language Server Protocol over standard input and output, for editors to show syntax
+// errors in pal files and offer hover, go-to-definition and completion.
+//
+//     pal-lsp
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if let Err(e) = pal::serve_lsp(stdin.lock(), stdout.lock()) {
+        writeln!(io::stderr(), "pal-lsp: {}", e).unwrap();
+        process::exit(1);
+    }
+}
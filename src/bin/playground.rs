@@ -0,0 +1,24 @@
+extern crate pal;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use pal::{Config, Limits};
+
+// Serves a page for running pal programs in the browser, which talks to the server over WebSocket.
+// Open http://localhost:8080/ (or whichever host and port are given) to use it.
+//
+//     playground [--host HOST] [--port PORT]
+fn main() {
+    let listener = match pal::listen_from_args("playground", env::args().skip(1), 8080) {
+        Ok(listener) => listener,
+        Err(message) => {
+            writeln!(io::stderr(), "{}", message).unwrap();
+            process::exit(1);
+        }
+    };
+
+    let config = Config { limits: Limits::for_server(), ..Config::default() };
+    pal::serve_websocket(listener, config);
+}
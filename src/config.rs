@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+// Options controlling how programs are run.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Whether int arithmetic that overflows should switch to arbitrary precision rather than
+    // failing with an `OverflowError`.
+    pub big_ints: bool,
+    // Limits on the resources the program can use.
+    pub limits: Limits,
+}
+
+// Limits on the resources a program can use, e.g. so that a server running programs it was sent
+// can't be tied up by one of them forever. A program that goes over a limit stops with a
+// `ResourceLimitError`. `None` means there's no limit.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    // The number of statements that can be run. Each time a block is run counts as a statement as
+    // well, so that even an empty loop will stop eventually.
+    pub max_statements: Option<u64>,
+    // How many function calls can be in progress at once.
+    pub max_call_depth: Option<usize>,
+    // The number of elements an array can have, entries a map can have or characters a string can
+    // have.
+    pub max_len: Option<usize>,
+    // How long the program can run for, not counting time spent waiting for input.
+    pub timeout: Option<Duration>,
+    // How long the program can wait for a line of input before it's cancelled, so that a client that
+    // stops answering can't keep it waiting forever. Only the servers enforce this.
+    pub input_timeout: Option<Duration>,
+}
+
+impl Limits {
+    // Limits for running programs that can't be trusted to finish, e.g. ones sent to a server, so
+    // that they can't tie it up.
+    pub fn for_server() -> Self {
+        Limits {
+            max_statements: Some(10_000_000),
+            max_call_depth: Some(256),
+            max_len: Some(1_000_000),
+            timeout: Some(Duration::from_secs(10)),
+            input_timeout: Some(Duration::from_secs(300)),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use diagnostic::{render_error, Style};
+use error::PalError;
+
+// Where a program's output goes and where its input comes from. The evaluator does all of its I/O
+// through one of these, so programs can be run with their I/O redirected, e.g. to a `Buffer` in
+// tests or to a `Stream` read by another thread.
+pub trait Console {
+    // Writes part of the output of the program, e.g. the value given to `print`.
+    fn write_output(&self, s: &str);
+
+    // Reads a line of input for `read_line`, without the newline at the end.
+    fn read_line(&self) -> String;
+
+    // Reports an error that stopped the program. `file` and `text` are the name and text of the
+    // program, for showing where the error happened.
+    fn report_error(&self, e: &PalError, file: &str, text: &str);
+
+    // Whether the host has asked for the program to stop. It's checked before each statement.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+// The standard input, output and error of the process.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stdio;
+
+impl Console for Stdio {
+    fn write_output(&self, s: &str) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(s.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn read_line(&self) -> String {
+        let mut buf = String::new();
+        let _ = io::stdin().read_line(&mut buf);
+
+        // Drop the line ending, which is `\r\n` on Windows.
+        if buf.ends_with('\n') {
+            let _ = buf.pop();
+
+            if buf.ends_with('\r') {
+                let _ = buf.pop();
+            }
+        }
+
+        buf
+    }
+
+    fn report_error(&self, e: &PalError, file: &str, text: &str) {
+        let _ = write!(io::stderr(), "{}", render_error(e, file, text, Style::for_stderr()));
+    }
+}
+
+// Keeps the output and errors of a program in memory, and gives it input from a list of lines given
+// beforehand. Once the lines run out, `read_line` gives empty strings, as at the end of a file.
+#[derive(Debug, Default)]
+pub struct Buffer {
+    input: Mutex<VecDeque<String>>,
+    output: Mutex<String>,
+    errors: Mutex<String>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer::default()
+    }
+
+    pub fn with_input(lines: &[&str]) -> Self {
+        let buffer = Buffer::new();
+
+        for line in lines {
+            buffer.push_input(line);
+        }
+
+        buffer
+    }
+
+    // Adds a line to the end of the input.
+    pub fn push_input(&self, line: &str) {
+        self.input.lock().unwrap().push_back(String::from(line));
+    }
+
+    // Returns everything the program has written so far.
+    pub fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+
+    // Returns every error that has been reported so far.
+    pub fn errors(&self) -> String {
+        self.errors.lock().unwrap().clone()
+    }
+}
+
+impl Console for Buffer {
+    fn write_output(&self, s: &str) {
+        self.output.lock().unwrap().push_str(s);
+    }
+
+    fn read_line(&self) -> String {
+        self.input.lock().unwrap().pop_front().unwrap_or_default()
+    }
+
+    fn report_error(&self, e: &PalError, file: &str, text: &str) {
+        self.errors.lock().unwrap().push_str(&render_error(e, file, text, Style::Plain));
+    }
+}
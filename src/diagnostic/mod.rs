@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod test;
+
+use std::cmp;
+use std::fmt::Write;
+
+use libc;
+
+use error::PalError;
+
+const BOLD: &'static str = "\x1b[1m";
+const BLUE: &'static str = "\x1b[1;34m";
+const RED: &'static str = "\x1b[1;31m";
+const RESET: &'static str = "\x1b[0m";
+
+// How a diagnostic should be rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    // Plain text, for output that isn't going to a terminal.
+    Plain,
+    // Text colored with ANSI escape codes.
+    Ansi,
+}
+
+impl Style {
+    // Uses colors only if standard error is a terminal.
+    pub fn for_stderr() -> Self {
+        if unsafe { libc::isatty(libc::STDERR_FILENO) } == 1 {
+            Style::Ansi
+        } else {
+            Style::Plain
+        }
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        match *self {
+            Style::Plain => String::from(s),
+            Style::Ansi => format!("{}{}{}", color, s, RESET),
+        }
+    }
+}
+
+// Renders an error along with the line of source it happened on, underlining the offending code:
+//
+//     TypeError: `true` is not an int, so `x + true` is invalid
+//      --> test.pal:2:9
+//       |
+//     2 | let y = x + true;
+//       |         ^^^^^^^^
+//       = note: This happened inside the function `foo`
+pub fn render_error(e: &PalError, file: &str, text: &str, style: Style) -> String {
+    // Render each error separately, with a blank line between them.
+    if let PalError::Multiple(ref errors) = *e {
+        let rendered: Vec<_> = errors.iter().map(|e| render_error(e, file, text, style)).collect();
+        return rendered.join("\n");
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}: {}", style.paint(RED, &e.kind_name()), style.paint(BOLD, &e.message()));
+
+    let span = match e.span() {
+        Some(span) => span,
+        None => {
+            for note in e.notes() {
+                let _ = writeln!(out, "{} {}", style.paint(BLUE, "= note:"), note);
+            }
+
+            return out;
+        }
+    };
+
+    let (line, column) = span.line_col(text);
+    let line_num = format!("{}", line);
+    let gutter: String = line_num.chars().map(|_| ' ').collect();
+
+    // Find the text of the line containing the start of the span.
+    let lo = cmp::min(span.lo, text.len());
+    let line_start = text[..lo].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = text[lo..].find('\n').map(|i| lo + i).unwrap_or(text.len());
+    let line_text = text[line_start..line_end].trim_right_matches('\r');
+
+    // Only underline up to the end of the line, but always underline at least one character.
+    let hi = cmp::max(lo, cmp::min(span.hi, line_start + line_text.len()));
+    let underline_len = cmp::max(1, text[lo..hi].chars().count());
+
+    // Keep tabs in the padding before the underline so that it lines up with the source.
+    let padding: String = text[line_start..lo].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let underline: String = (0..underline_len).map(|_| '^').collect();
+
+    let _ = writeln!(out, "{}{} {}:{}:{}", gutter, style.paint(BLUE, "-->"), file, line, column);
+    let _ = writeln!(out, "{} {}", gutter, style.paint(BLUE, "|"));
+    let _ = writeln!(out, "{} {}", style.paint(BLUE, &format!("{} |", line_num)), line_text);
+    let _ = writeln!(out, "{} {} {}{}", gutter, style.paint(BLUE, "|"), padding, style.paint(RED, &underline));
+
+    for note in e.notes() {
+        let _ = writeln!(out, "{} {} {}", gutter, style.paint(BLUE, "= note:"), note);
+    }
+
+    out
+}
@@ -0,0 +1,63 @@
+use console::Stdio;
+use diagnostic::{render_error, Style};
+use error::PalError;
+use parser::{parse_program, parse_stmt};
+use state::State;
+
+#[test]
+fn render_runtime_error() {
+    let text = "let x = 1;\nlet y = x + true;\n";
+    let program = parse_program(text).0;
+
+    let mut state = State::new();
+    program[0].eval(&mut state, &Stdio).unwrap();
+    let err = PalError::from(program[1].eval(&mut state, &Stdio).unwrap_err());
+
+    let expected = "TypeError: `true` is not a number, so `x + true` is invalid\n \
+                    --> test.pal:2:9\n  \
+                    |\n\
+                    2 | let y = x + true;\n  \
+                    |         ^^^^^^^^\n";
+
+    assert_eq!(expected, render_error(&err, "test.pal", text, Style::Plain));
+}
+
+#[test]
+fn render_unrecognized_token() {
+    let text = "let x = 1 2;";
+    let err = PalError::from_parse_error(parse_stmt(text).1.remove(0), text);
+    let rendered = render_error(&err, "<repl>", text, Style::Plain);
+
+    assert!(rendered.starts_with("SyntaxError: `2` doesn't make sense here\n --> <repl>:1:11\n"));
+    assert!(rendered.contains("1 | let x = 1 2;\n  |           ^\n"));
+    assert!(rendered.contains("= note: Expected one of "));
+}
+
+#[test]
+fn render_unexpected_eof() {
+    let text = "let x = 1";
+    let err = PalError::from_parse_error(parse_stmt(text).1.remove(0), text);
+    let rendered = render_error(&err, "<repl>", text, Style::Plain);
+
+    assert!(rendered.starts_with("SyntaxError: The program ended in the middle of a statement\n --> <repl>:1:10\n"));
+    assert!(rendered.contains("1 | let x = 1\n  |          ^\n"));
+}
+
+#[test]
+fn render_multiple_errors() {
+    let text = "let x = 1 2;\nlet y = ;\n";
+    let err = PalError::from_parse_errors(parse_program(text).1, text).unwrap();
+    let rendered = render_error(&err, "test.pal", text, Style::Plain);
+
+    assert_eq!("SyntaxError", err.kind_name());
+    assert!(rendered.contains(" --> test.pal:1:11\n"));
+    assert!(rendered.contains(" --> test.pal:2:9\n"));
+}
+
+#[test]
+fn render_io_error() {
+    let err = ::run_file("does/not/exist.pal").unwrap_err();
+    let rendered = render_error(&err, "does/not/exist.pal", "", Style::Plain);
+
+    assert!(rendered.starts_with("IOError: Unable to read `does/not/exist.pal`: "));
+}
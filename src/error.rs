@@ -1,31 +1,104 @@
 use std::fmt;
+use std::io;
 use std::result;
 
+use ast::Span;
+use lalrpop_util;
+use parser::ParseError;
+use token;
+
 pub type Result<T> = result::Result<T, Error>;
+pub type PalResult<T> = result::Result<T, PalError>;
 
 #[derive(Debug)]
 pub struct Error {
     err: ErrorType,
     message: String,
+    // The span of the innermost statement or expression that caused the error.
+    span: Option<Span>,
+    // The file, line and column of the span, once the source text is known.
+    location: Option<Location>,
+    // Extra context about the error, e.g. which function it happened in.
+    notes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}:{}", self.file, self.line, self.column)
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref location) = self.location {
+            try!(write!(fmt, "{}: ", location));
+        }
+
         write!(fmt, "{}: {}", self.err, self.message)
     }
 }
 
 impl Error {
     pub fn new(t: ErrorType, s: &str) -> Self {
-        Error { err: t, message: String::from(s) }
+        Error { err: t, message: String::from(s), span: None, location: None, notes: Vec::new() }
     }
 
     #[inline]
-    #[allow(dead_code)]
     pub fn err_type(&self) -> ErrorType {
         self.err.clone()
     }
 
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    #[inline]
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    // Attaches a span to the error unless a more specific one has already been attached.
+    pub fn with_span(mut self, span: Span) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+
+        self
+    }
+
+    // Adds a note giving extra context about the error. A note that's the same as the last one, e.g.
+    // from a function calling itself, isn't repeated.
+    pub fn with_note(mut self, note: String) -> Self {
+        if self.notes.last() != Some(&note) {
+            self.notes.push(note);
+        }
+
+        self
+    }
+
+    // Resolves the span of the error into a file, line and column using the text of the program.
+    pub fn locate(mut self, file: &str, text: &str) -> Self {
+        if let Some(span) = self.span {
+            let (line, column) = span.line_col(text);
+            self.location = Some(Location { file: String::from(file), line: line, column: column });
+        }
+
+        self
+    }
+
     pub fn argument_error<T>(s: &str) -> Result<T> {
         Err(Self::new(ErrorType::Argument, s))
     }
@@ -34,10 +107,38 @@ impl Error {
         Err(Self::new(ErrorType::ArrayIndexOutOfBounds, s))
     }
 
+    pub fn cancelled_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::Cancelled, s))
+    }
+
+    pub fn conversion_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::Conversion, s))
+    }
+
+    pub fn division_by_zero_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::DivisionByZero, s))
+    }
+
+    pub fn missing_key_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::MissingKey, s))
+    }
+
+    pub fn overflow_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::Overflow, s))
+    }
+
     pub fn redef_func_error<T>(s: &str) -> Result<T> {
         Err(Self::new(ErrorType::RedefinedFunction, s))
     }
 
+    pub fn redef_var_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::RedefinedVariable, s))
+    }
+
+    pub fn resource_limit_error<T>(s: &str) -> Result<T> {
+        Err(Self::new(ErrorType::ResourceLimit, s))
+    }
+
     pub fn step_error<T>(s: &str) -> Result<T> {
         Err(Self::new(ErrorType::Step, s))
     }
@@ -59,7 +160,15 @@ impl Error {
 pub enum ErrorType {
     Argument,
     ArrayIndexOutOfBounds,
+    Cancelled,
+    Conversion,
+    DivisionByZero,
+    MissingKey,
+    Native,
+    Overflow,
     RedefinedFunction,
+    RedefinedVariable,
+    ResourceLimit,
     Step,
     Type,
     UndefinedFunction,
@@ -71,7 +180,15 @@ impl fmt::Display for ErrorType {
         match *self {
             ErrorType::Argument => write!(fmt, "ArgumentError"),
             ErrorType::ArrayIndexOutOfBounds => write!(fmt, "ArrayIndexOutOfBoundsError"),
+            ErrorType::Cancelled => write!(fmt, "CancelledError"),
+            ErrorType::Conversion => write!(fmt, "ConversionError"),
+            ErrorType::DivisionByZero => write!(fmt, "DivisionByZeroError"),
+            ErrorType::MissingKey => write!(fmt, "MissingKeyError"),
+            ErrorType::Native => write!(fmt, "NativeError"),
+            ErrorType::Overflow => write!(fmt, "OverflowError"),
             ErrorType::RedefinedFunction => write!(fmt, "RedefinedFunctionError"),
+            ErrorType::RedefinedVariable => write!(fmt, "RedefinedVariableError"),
+            ErrorType::ResourceLimit => write!(fmt, "ResourceLimitError"),
             ErrorType::Step => write!(fmt, "StepError"),
             ErrorType::Type => write!(fmt, "TypeError"),
             ErrorType::UndefinedFunction => write!(fmt, "UndefinedFunctionError"),
@@ -79,3 +196,137 @@ impl fmt::Display for ErrorType {
         }
     }
 }
+
+// Any error that can happen while loading, parsing or running a program.
+#[derive(Debug)]
+pub enum PalError {
+    // The program couldn't be read from the given file.
+    Io(String, io::Error),
+    // The tokenizer found something that isn't part of the language.
+    Token(token::Error),
+    // The parser was given an invalid token at the given offset.
+    InvalidToken(usize),
+    // The parser found a token it didn't expect; the list is of the tokens it would have accepted.
+    UnrecognizedToken(String, Span, Vec<String>),
+    // The program ended in the middle of a statement.
+    UnexpectedEof(usize, Vec<String>),
+    // The parser found a token after the end of a complete statement.
+    ExtraToken(String, Span),
+    // The program failed while it was running.
+    Runtime(Error),
+    // More than one error was found, e.g. several syntax errors in the same program.
+    Multiple(Vec<PalError>),
+}
+
+impl PalError {
+    // Converts an error from the parser. The text of the program is needed to point at the end of
+    // the input when the parser runs out of tokens.
+    pub fn from_parse_error(e: ParseError, text: &str) -> Self {
+        match e {
+            lalrpop_util::ParseError::InvalidToken { location } => PalError::InvalidToken(location),
+            lalrpop_util::ParseError::UnrecognizedToken { token: Some((lo, t, hi)), expected } =>
+                PalError::UnrecognizedToken(format!("{}", t), Span::new(lo, hi), expected),
+            lalrpop_util::ParseError::UnrecognizedToken { token: None, expected } =>
+                PalError::UnexpectedEof(text.len(), expected),
+            lalrpop_util::ParseError::ExtraToken { token: (lo, t, hi) } =>
+                PalError::ExtraToken(format!("{}", t), Span::new(lo, hi)),
+            lalrpop_util::ParseError::User { error } => PalError::Token(error),
+        }
+    }
+
+    // Converts all of the syntax errors found in a program, returning `None` if there weren't any.
+    pub fn from_parse_errors(errors: Vec<ParseError>, text: &str) -> Option<Self> {
+        let mut errors: Vec<_> = errors.into_iter().map(|e| Self::from_parse_error(e, text)).collect();
+
+        match errors.len() {
+            0 => None,
+            1 => errors.pop(),
+            _ => Some(PalError::Multiple(errors)),
+        }
+    }
+
+    // Returns the name of the kind of error, e.g. "SyntaxError".
+    pub fn kind_name(&self) -> String {
+        match *self {
+            PalError::Io(..) => String::from("IOError"),
+            PalError::Runtime(ref e) => format!("{}", e.err_type()),
+            PalError::Multiple(ref errors) if errors.iter().all(|e| e.kind_name() == "SyntaxError") =>
+                String::from("SyntaxError"),
+            PalError::Multiple(_) => String::from("Error"),
+            _ => String::from("SyntaxError"),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match *self {
+            PalError::Io(ref file, ref e) => format!("Unable to read `{}`: {}", file, e),
+            PalError::Token(ref e) => e.message.clone(),
+            PalError::InvalidToken(_) => String::from("This isn't a valid token"),
+            PalError::UnrecognizedToken(ref token, _, _) => format!("`{}` doesn't make sense here", token),
+            PalError::UnexpectedEof(..) => String::from("The program ended in the middle of a statement"),
+            PalError::ExtraToken(ref token, _) =>
+                format!("`{}` doesn't make sense after the end of the statement", token),
+            PalError::Runtime(ref e) => String::from(e.message()),
+            PalError::Multiple(ref errors) => format!("{} errors were found", errors.len()),
+        }
+    }
+
+    // Returns the part of the program that caused the error, if any.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            PalError::Io(..) | PalError::Multiple(_) => None,
+            PalError::Token(ref e) => Some(Span::new(e.location, e.location + 1)),
+            PalError::InvalidToken(location) => Some(Span::new(location, location + 1)),
+            PalError::UnrecognizedToken(_, span, _) | PalError::ExtraToken(_, span) => Some(span),
+            PalError::UnexpectedEof(end, _) => Some(Span::new(end, end)),
+            PalError::Runtime(ref e) => e.span(),
+        }
+    }
+
+    pub fn notes(&self) -> Vec<String> {
+        match *self {
+            PalError::UnrecognizedToken(_, _, ref expected) | PalError::UnexpectedEof(_, ref expected) =>
+                expected_note(expected).into_iter().collect(),
+            PalError::Runtime(ref e) => Vec::from(e.notes()),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Describes the tokens the parser would have accepted. LALRPOP gives them in quotes (e.g. `";"`),
+// so they're stripped and replaced with backticks.
+fn expected_note(expected: &[String]) -> Option<String> {
+    let tokens: Vec<_> = expected.iter().map(|t| format!("`{}`", t.trim_matches('"'))).collect();
+
+    match tokens.len() {
+        0 => None,
+        1 => Some(format!("Expected {}", tokens[0])),
+        _ => Some(format!("Expected one of {}", tokens.join(", "))),
+    }
+}
+
+impl fmt::Display for PalError {
+    fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PalError::Runtime(ref e) => write!(fmt, "{}", e),
+            PalError::Multiple(ref errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i != 0 {
+                        try!(writeln!(fmt, ""));
+                    }
+
+                    try!(write!(fmt, "{}", e));
+                }
+
+                Ok(())
+            }
+            _ => write!(fmt, "{}: {}", self.kind_name(), self.message()),
+        }
+    }
+}
+
+impl From<Error> for PalError {
+    fn from(e: Error) -> Self {
+        PalError::Runtime(e)
+    }
+}
@@ -1,3 +1,8 @@
+use std::cmp::Ordering;
+use std::f64;
+
+use num::{BigInt, Signed, ToPrimitive};
+
 use ast::{Expr, Value};
 use error::{Error, Result};
 
@@ -13,42 +18,130 @@ pub fn bool_exp<F>(exp: &Expr, val1: Value, val2: Value, func: F) -> Result<Valu
     }
 }
 
-// Evaluate a binary expression on two same-typed values yielding a boolean.
+// Evaluate a binary expression on two same-typed values yielding a boolean. Ints and floats can be
+// compared with each other; the int is converted to a float first.
 pub fn eq_exp<F>(exp: &Expr, val1: Value, val2: Value, func: F) -> Result<Value>
     where F: Fn(Value, Value) -> bool {
+    match (&val1, &val2) {
+        (&Value::Float(_), &Value::Int(_)) | (&Value::Float(_), &Value::BigInt(_)) |
+        (&Value::Int(_), &Value::Float(_)) | (&Value::BigInt(_), &Value::Float(_)) =>
+            return Ok(Value::Bool(func(Value::Float(to_f64(&val1)), Value::Float(to_f64(&val2))))),
+        _ => (),
+    }
+
     match (&val1, &val2) {
         (&Value::Bool(_), &Value::Bool(_)) |
+        (&Value::Float(_), &Value::Float(_)) |
         (&Value::Int(_), &Value::Int(_)) |
+        (&Value::Int(_), &Value::BigInt(_)) |
+        (&Value::BigInt(_), &Value::Int(_)) |
+        (&Value::BigInt(_), &Value::BigInt(_)) |
         (&Value::Str(_), &Value::Str(_)) |
-        (&Value::Array(_), &Value::Array(_)) => Ok(Value::Bool(func(val1, val2))),
+        (&Value::Array(_), &Value::Array(_)) |
+        (&Value::Map(_), &Value::Map(_)) |
+        (&Value::Function(_), &Value::Function(_)) => Ok(Value::Bool(func(val1, val2))),
         _ => Error::type_error(
             &format!("`{}` is {} and `{}` is {}, so `{}` doesn't make sense",
                      val1, val1.type_string_with_article(), val2, val2.type_string_with_article(), exp)),
     }
 }
 
-// Evaluate a binary expression on two int values yielding a boolean.
+// Evaluate a binary expression on two numeric values yielding a boolean based on how they compare.
+// Ints are compared exactly; if either value is a float, both are compared as floats, in which case
+// nothing compares to NaN.
 pub fn ineq_exp<F>(exp: &Expr, val1: Value, val2: Value, func: F) -> Result<Value>
-    where F: Fn(i64, i64) -> bool {
-    match (&val1, &val2) {
-        (&Value::Int(i1), &Value::Int(i2)) => Ok(Value::Bool(func(i1, i2))),
-        (&Value::Int(_), _) => Error::type_error(
-            &format!("`{}` is not an int, so `{}` is invalid", val2, exp)),
-        _ => Error::type_error(
-            &format!("`{}` is not an int, so `{}` is invalid", val1, exp)),
+    where F: Fn(Ordering) -> bool {
+    try!(check_numbers(exp, &val1, &val2));
+    Ok(Value::Bool(compare_numbers(&val1, &val2).map_or(false, func)))
+}
+
+// Compares two numbers the way `ineq_exp` does, returning `None` if either is NaN or isn't a number.
+pub fn compare_numbers(val1: &Value, val2: &Value) -> Option<Ordering> {
+    if !is_number(val1) || !is_number(val2) {
+        return None;
+    }
 
+    match (val1, val2) {
+        (&Value::Int(i1), &Value::Int(i2)) => Some(i1.cmp(&i2)),
+        (&Value::Float(_), _) | (_, &Value::Float(_)) => to_f64(val1).partial_cmp(&to_f64(val2)),
+        _ => Some(to_big(val1).cmp(&to_big(val2))),
     }
 }
 
-// Evaluate a binary expression on two int values yielding an int.
-pub fn arith_exp<F>(exp: &Expr, val1: Value, val2: Value, func: F) -> Result<Value>
-    where F: Fn(i64, i64) -> i64 {
+// Evaluate a binary expression on two numeric values. Two ints yield an int; if either value is a
+// float, the other is converted to a float and the result is a float.
+//
+// The int functions return `None` when the operation fails. Since only division and modulus can fail
+// when the right-hand side is zero, that's taken to mean division by zero; otherwise, the result was
+// too big for an int, and is either recomputed with arbitrary precision if `big_ints` is set or an
+// error.
+pub fn arith_exp<F, G, H>(exp: &Expr, val1: Value, val2: Value, big_ints: bool, int_func: F, float_func: G,
+                          big_func: H) -> Result<Value>
+    where F: Fn(i64, i64) -> Option<i64>, G: Fn(f64, f64) -> f64, H: Fn(&BigInt, &BigInt) -> Option<BigInt> {
+    try!(check_numbers(exp, &val1, &val2));
+
     match (&val1, &val2) {
-        (&Value::Int(i1), &Value::Int(i2)) => Ok(Value::Int(func(i1, i2))),
-        (&Value::Int(_), _) => Error::type_error(
-            &format!("`{}` is not an int, so `{}` is invalid", val2, exp)),
-        _ => Error::type_error(
-            &format!("`{}` is not an int, so `{}` is invalid", val1, exp)),
+        (&Value::Int(i1), &Value::Int(i2)) => match int_func(i1, i2) {
+            Some(i) => Ok(Value::Int(i)),
+            None if i2 == 0 => division_by_zero_error(exp),
+            None if big_ints => big_arith(exp, &BigInt::from(i1), &BigInt::from(i2), big_func),
+            None => Error::overflow_error(&format!("The result of `{}` is too big to fit in an int", exp)),
+        },
+        (&Value::Float(_), _) | (_, &Value::Float(_)) => Ok(Value::Float(float_func(to_f64(&val1), to_f64(&val2)))),
+        _ => big_arith(exp, &to_big(&val1), &to_big(&val2), big_func),
+    }
+}
+
+// Evaluate an arithmetic expression with arbitrary precision, converting the result back to a
+// regular int if it's small enough.
+fn big_arith<H>(exp: &Expr, b1: &BigInt, b2: &BigInt, big_func: H) -> Result<Value>
+    where H: Fn(&BigInt, &BigInt) -> Option<BigInt> {
+    match big_func(b1, b2) {
+        Some(b) => Ok(match b.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::BigInt(b),
+        }),
+        None => division_by_zero_error(exp),
+    }
+}
+
+fn division_by_zero_error<T>(exp: &Expr) -> Result<T> {
+    Error::division_by_zero_error(&format!("`{}` divides by zero", exp))
+}
+
+// Returns an error unless both values are numbers.
+fn check_numbers(exp: &Expr, val1: &Value, val2: &Value) -> Result<()> {
+    if !is_number(val1) {
+        Error::type_error(&format!("`{}` is not a number, so `{}` is invalid", val1, exp))
+    } else if !is_number(val2) {
+        Error::type_error(&format!("`{}` is not a number, so `{}` is invalid", val2, exp))
+    } else {
+        Ok(())
+    }
+}
+
+fn is_number(val: &Value) -> bool {
+    match *val {
+        Value::BigInt(_) | Value::Float(_) | Value::Int(_) => true,
+        _ => false,
+    }
+}
+
+// Converts a number to a float, rounding big ints that are out of range to infinity.
+fn to_f64(val: &Value) -> f64 {
+    match *val {
+        Value::BigInt(ref b) => b.to_f64().unwrap_or(if b.is_negative() { f64::NEG_INFINITY } else { f64::INFINITY }),
+        Value::Float(f) => f,
+        Value::Int(i) => i as f64,
+        _ => f64::NAN,
+    }
+}
 
+// Converts an int to a big int.
+fn to_big(val: &Value) -> BigInt {
+    match *val {
+        Value::BigInt(ref b) => b.clone(),
+        Value::Int(i) => BigInt::from(i),
+        _ => BigInt::from(0),
     }
 }
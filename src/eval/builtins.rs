@@ -0,0 +1,217 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use ast::Value;
+use console::Console;
+use error::{Error, Result};
+use eval::bin_exp::compare_numbers;
+use state::State;
+
+// The names of the functions that are built into the language rather than being keywords. A
+// variable or function defined with one of these names takes its place.
+pub const BUILTINS: &'static [&'static str] =
+    &["contains", "filter", "index_of", "join", "map", "reduce", "reverse", "slice", "sort"];
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+// Calls a builtin function with the values of its arguments. `call` is the expression calling it,
+// for error messages.
+pub fn call(name: &str, call: &Display, mut args: Vec<Value>, state: &mut State,
+            console: &Console) -> Result<Value> {
+    match name {
+        "contains" => {
+            try!(check_arg_count(name, &args, 2, 2));
+            let val = args.pop().unwrap();
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+
+            Ok(Value::Bool(vec.contains(&val)))
+        }
+        "filter" => {
+            try!(check_arg_count(name, &args, 2, 2));
+            let func = args.pop().unwrap();
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+            let mut out = Vec::new();
+
+            for val in vec {
+                match try!(call_arg(&func, vec![val.clone()], call, state, console)) {
+                    Value::Bool(true) => out.push(val),
+                    Value::Bool(false) => (),
+                    result => return Error::type_error(
+                        &format!("The function given to `{}` returned {} instead of a boolean",
+                                 call, result.type_string_with_article())),
+                }
+            }
+
+            Ok(Value::Array(out))
+        }
+        "index_of" => {
+            try!(check_arg_count(name, &args, 2, 2));
+            let val = args.pop().unwrap();
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+
+            Ok(Value::Int(vec.iter().position(|v| *v == val).map_or(-1, |i| i as i64)))
+        }
+        "join" => {
+            try!(check_arg_count(name, &args, 2, 2));
+            let sep = match args.pop().unwrap() {
+                Value::Str(s) => s,
+                val => return Error::type_error(
+                    &format!("The separator is {}, so `{}` doesn't make sense", val.type_string_with_article(), call)),
+            };
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+            let strings: Vec<_> = vec.iter().map(|v| format!("{}", v)).collect();
+
+            Ok(Value::Str(strings.join(&sep)))
+        }
+        "map" => {
+            try!(check_arg_count(name, &args, 2, 2));
+            let func = args.pop().unwrap();
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+            let mut out = Vec::new();
+
+            for val in vec {
+                out.push(try!(call_arg(&func, vec![val], call, state, console)));
+            }
+
+            Ok(Value::Array(out))
+        }
+        "reduce" => {
+            try!(check_arg_count(name, &args, 3, 3));
+            let mut acc = args.pop().unwrap();
+            let func = args.pop().unwrap();
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+
+            for val in vec {
+                acc = try!(call_arg(&func, vec![acc, val], call, state, console));
+            }
+
+            Ok(acc)
+        }
+        "reverse" => {
+            try!(check_arg_count(name, &args, 1, 1));
+
+            match args.pop().unwrap() {
+                Value::Array(mut vec) => {
+                    vec.reverse();
+                    Ok(Value::Array(vec))
+                }
+                Value::Str(s) => Ok(Value::Str(s.chars().rev().collect())),
+                val => Error::type_error(
+                    &format!("`{}` needs an array or a string, but was given {}", call, val.type_string_with_article())),
+            }
+        }
+        "slice" => {
+            try!(check_arg_count(name, &args, 3, 3));
+            let end = try!(int_arg(args.pop().unwrap(), call));
+            let start = try!(int_arg(args.pop().unwrap(), call));
+            let vec = try!(array_arg(args.pop().unwrap(), call));
+
+            if start < 0 || end < start || end as usize > vec.len() {
+                return Error::array_index_out_of_bounds_error(
+                    &format!("The array has {} elements in it, so `{}` doesn't make sense", vec.len(), call));
+            }
+
+            Ok(Value::Array(vec[start as usize..end as usize].to_vec()))
+        }
+        "sort" => {
+            try!(check_arg_count(name, &args, 1, 2));
+            let func = if args.len() == 2 { args.pop() } else { None };
+            let mut vec = try!(array_arg(args.pop().unwrap(), call));
+            let mut error = None;
+
+            // `sort_by` can't fail, so the first error is kept and the rest of the comparisons are
+            // skipped.
+            vec.sort_by(|a, b| {
+                if error.is_some() {
+                    return Ordering::Equal;
+                }
+
+                let result = match func {
+                    Some(ref func) => compare_with(func, a, b, call, state, console),
+                    None => compare(a, b, call),
+                };
+
+                result.unwrap_or_else(|e| {
+                    error = Some(e);
+                    Ordering::Equal
+                })
+            });
+
+            match error {
+                Some(e) => Err(e),
+                None => Ok(Value::Array(vec)),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn check_arg_count(name: &str, args: &[Value], min: usize, max: usize) -> Result<()> {
+    if args.len() >= min && args.len() <= max {
+        return Ok(());
+    }
+
+    let expected = if min == max { format!("{}", min) } else { format!("{} or {}", min, max) };
+
+    Error::argument_error(
+        &format!("The function `{}` takes {} arguments, but {} were given", name, expected, args.len()))
+}
+
+fn array_arg(val: Value, call: &Display) -> Result<Vec<Value>> {
+    match val {
+        Value::Array(vec) => Ok(vec),
+        _ => Error::type_error(
+            &format!("`{}` needs an array, but was given {}", call, val.type_string_with_article())),
+    }
+}
+
+fn int_arg(val: Value, call: &Display) -> Result<i64> {
+    match val {
+        Value::Int(i) => Ok(i),
+        _ => Error::type_error(
+            &format!("`{}` needs an int, but was given {}", call, val.type_string_with_article())),
+    }
+}
+
+// Calls a function given as an argument to a builtin, which has to return a value.
+fn call_arg(func: &Value, args: Vec<Value>, call: &Display, state: &mut State,
+            console: &Console) -> Result<Value> {
+    let func = match *func {
+        Value::Function(ref func) => func.clone(),
+        ref val => return Error::type_error(
+            &format!("`{}` needs a function, but was given {}", call, val.type_string_with_article())),
+    };
+
+    match try!(state.call(func, args, console)) {
+        Some(val) => Ok(val),
+        None => Error::type_error(
+            &format!("The function given to `{}` doesn't return anything", call)),
+    }
+}
+
+// Compares two values with a comparator function, which returns a negative int if the first value
+// comes first, a positive int if the second does, and zero if they're equal.
+fn compare_with(func: &Value, a: &Value, b: &Value, call: &Display, state: &mut State,
+                console: &Console) -> Result<Ordering> {
+    match try!(call_arg(func, vec![a.clone(), b.clone()], call, state, console)) {
+        Value::Int(i) => Ok(i.cmp(&0)),
+        val => Error::type_error(
+            &format!("The comparator given to `{}` returned {} instead of an int", call, val.type_string_with_article())),
+    }
+}
+
+// Compares two values in their natural order; only numbers and strings can be compared.
+fn compare(a: &Value, b: &Value, call: &Display) -> Result<Ordering> {
+    let ordering = match (a, b) {
+        (&Value::Str(ref s1), &Value::Str(ref s2)) => Some(s1.cmp(s2)),
+        _ => compare_numbers(a, b),
+    };
+
+    match ordering {
+        Some(o) => Ok(o),
+        None => Error::type_error(
+            &format!("`{}` and `{}` can't be compared, so `{}` doesn't make sense", a, b, call)),
+    }
+}
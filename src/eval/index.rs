@@ -0,0 +1,158 @@
+use std::fmt::Display;
+
+use ast::{Expr, Value};
+use console::Console;
+use error::{Error, Result};
+use state::State;
+
+// An index into an array or map (e.g. the `1` in `a[1]`) that has been evaluated, along with the
+// expression it came from so that it can be shown in error messages.
+pub type Index<'a> = (&'a Expr, Value);
+
+// Evaluates the indexes of an element like `a[i][j]` from left to right.
+pub fn eval_indexes<'a>(index: &'a Expr, indexes: &'a [Expr], state: &mut State,
+                        console: &Console) -> Result<Vec<Index<'a>>> {
+    let mut out = vec![(index, try!(index.eval(state, console)))];
+
+    for idx in indexes {
+        out.push((idx, try!(idx.eval(state, console))));
+    }
+
+    Ok(out)
+}
+
+// Looks up the element at the end of a chain of indexes, starting from the value of the variable
+// `var`. `source` is the expression or statement doing the lookup, for error messages.
+pub fn get_path<'a>(root: &'a Value, var: &str, indexes: &[Index], source: &Display) -> Result<&'a Value> {
+    let mut repr = String::from(var);
+    let mut curr = root;
+
+    for index in indexes {
+        curr = try!(get(curr, index, &repr, source));
+        repr.push_str(&format!("[{}]", index.0));
+    }
+
+    Ok(curr)
+}
+
+// Like `get_path`, but returns a mutable reference along with how the element is written in the
+// program.
+pub fn get_path_mut<'a>(root: &'a mut Value, var: &str, indexes: &[Index],
+                        source: &Display) -> Result<(&'a mut Value, String)> {
+    let mut repr = String::from(var);
+    let mut curr = root;
+
+    for index in indexes {
+        let container = curr;
+        curr = try!(get_mut(container, index, &repr, source));
+        repr.push_str(&format!("[{}]", index.0));
+    }
+
+    Ok((curr, repr))
+}
+
+// Looks up the element of an array or map at an index. `repr` is how the container is written in
+// the program.
+fn get<'a>(container: &'a Value, index: &Index, repr: &str, source: &Display) -> Result<&'a Value> {
+    match *container {
+        Value::Array(ref vec) => array_position(vec, index, repr, source).map(|i| &vec[i]),
+        Value::Map(ref map) => {
+            let key = try!(map_key(index, source));
+
+            match map.get(key) {
+                Some(val) => Ok(val),
+                None => missing_key_error(repr, key, source),
+            }
+        }
+        ref val => not_indexable_error(repr, val, source),
+    }
+}
+
+fn get_mut<'a>(container: &'a mut Value, index: &Index, repr: &str, source: &Display) -> Result<&'a mut Value> {
+    match *container {
+        Value::Array(ref mut vec) => {
+            let i = try!(array_position(vec, index, repr, source));
+            Ok(&mut vec[i])
+        }
+        Value::Map(ref mut map) => {
+            let key = try!(map_key(index, source));
+
+            match map.get_mut(key) {
+                Some(val) => Ok(val),
+                None => missing_key_error(repr, key, source),
+            }
+        }
+        ref val => not_indexable_error(repr, val, source),
+    }
+}
+
+// Sets the element of an array or map at an index. Assigning to a key that isn't in a map adds it;
+// arrays can only have existing elements assigned to.
+pub fn set(container: &mut Value, index: &Index, val: Value, repr: &str, source: &Display) -> Result<()> {
+    match *container {
+        Value::Array(ref mut vec) => {
+            let i = try!(array_position(vec, index, repr, source));
+            vec[i] = val;
+            Ok(())
+        }
+        Value::Map(ref mut map) => {
+            let key = try!(map_key(index, source));
+            map.insert(String::from(key), val);
+            Ok(())
+        }
+        ref val => not_indexable_error(repr, val, source),
+    }
+}
+
+// Removes the element of an array or map at an index; later elements of an array are shifted down.
+pub fn remove(container: &mut Value, index: &Index, repr: &str, source: &Display) -> Result<()> {
+    match *container {
+        Value::Array(ref mut vec) => {
+            let i = try!(array_position(vec, index, repr, source));
+            vec.remove(i);
+            Ok(())
+        }
+        Value::Map(ref mut map) => {
+            let key = try!(map_key(index, source));
+
+            match map.remove(key) {
+                Some(_) => Ok(()),
+                None => missing_key_error(repr, key, source),
+            }
+        }
+        ref val => not_indexable_error(repr, val, source),
+    }
+}
+
+// Checks that an index is an int within the bounds of an array.
+fn array_position(vec: &[Value], index: &Index, repr: &str, source: &Display) -> Result<usize> {
+    match index.1 {
+        Value::Int(i) if i < 0 => Error::array_index_out_of_bounds_error(
+            &format!("`{}` is {}, so `{}` doesn't make sense", index.0, i, source)),
+        Value::Int(i) if i as usize >= vec.len() => Error::array_index_out_of_bounds_error(
+            &format!("`{}` has {} elements in it, so `{}` doesn't make sense", repr, vec.len(), source)),
+        Value::Int(i) => Ok(i as usize),
+        ref val => Error::type_error(
+            &format!("`{}` is {}, so `{}` doesn't make sense", index.0, val.type_string_with_article(), source)),
+    }
+}
+
+// Checks that the index of a map is a string.
+fn map_key<'a>(index: &'a Index, source: &Display) -> Result<&'a str> {
+    match index.1 {
+        Value::Str(ref key) => Ok(key),
+        ref val => Error::type_error(
+            &format!("`{}` is {}, but map keys have to be strings, so `{}` doesn't make sense",
+                     index.0, val.type_string_with_article(), source)),
+    }
+}
+
+fn missing_key_error<T>(repr: &str, key: &str, source: &Display) -> Result<T> {
+    Error::missing_key_error(
+        &format!("`{}` doesn't have the key \"{}\", so `{}` doesn't make sense", repr, key, source))
+}
+
+fn not_indexable_error<T>(repr: &str, val: &Value, source: &Display) -> Result<T> {
+    Error::type_error(
+        &format!("`{}` is {}, so `{}` doesn't make sense", repr, val.type_string_with_article(), source))
+}
@@ -2,179 +2,200 @@
 mod test;
 
 mod bin_exp;
+mod builtins;
+mod index;
 
-use std::io::{self, Write};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::{f64, i64};
 use std::sync::Arc;
+use std::time::Instant;
 
-use ast::{BinOp, Expr, Statement, Value};
+use ast::{BinOp, Expr, ExprKind, Function, Statement, StmtKind, Value};
 use self::bin_exp::{arith_exp, bool_exp, eq_exp, ineq_exp};
+use self::index::eval_indexes;
+use console::Console;
 use error::{Error, Result};
 use state::State;
-use stream::Stream;
 
+pub use self::builtins::{call as call_builtin, BUILTINS};
+
+use num::{Signed, ToPrimitive, Zero};
 use stepper::Stepper;
 
-impl Statement {
-    pub fn eval(&self, state: &mut State, stream_opt: Option<Arc<Stream>>) -> Result<Option<Value>> {
-        match *self {
-            Statement::ArrayElemAssign(ref var, ref index, ref indexes, ref exp) => {
-                let mut array_vec = match state.lookup(var) {
-                    Some(&Value::Array(ref vec)) => vec.clone(),
-                    Some(ref val) => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, val.type_string_with_article(), self)),
-                    None => return Error::undef_var_error(
-                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
-                };
+// How control leaves a statement other than by running to the end of it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Flow {
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Value),
+}
 
-                let index_val = try!(index.eval(state, stream_opt.clone()));
-                let mut index_int = match index_val {
-                    Value::Int(i) => i,
-                    _ => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                };
+// Checks whether a `break` or `continue` with the given label applies to a loop; without a label,
+// it applies to the innermost loop.
+fn targets_loop(label: &Option<String>, loop_label: &Option<String>) -> bool {
+    label.is_none() || label == loop_label
+}
 
-                if index_int < 0 {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense",
-                        index, index_int, self))
-                }
+// Counts the ints from `start` to `end` inclusive when stepping by `step`, without overflowing.
+fn step_count(start: i64, end: i64, step: i64) -> usize {
+    // The differences are taken as unsigned ints so that they can't overflow.
+    let distance = if end >= start {
+        (end as u64).wrapping_sub(start as u64)
+    } else {
+        (start as u64).wrapping_sub(end as u64)
+    };
+    let step_size = if step < 0 { (step as u64).wrapping_neg() } else { step as u64 };
+
+    (distance / step_size).saturating_add(1) as usize
+}
 
-                if index_int as usize >= array_vec.len() {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                        var, array_vec.len(), self))
-                }
+// Checks whether the program should stop before running a statement or block, either because it
+// has been cancelled or because it has reached one of the limits in the config.
+fn count_statement(state: &mut State, console: &Console) -> Result<()> {
+    if console.is_cancelled() {
+        return Error::cancelled_error("The program was cancelled");
+    }
 
-                let mut repr = format!("{}[{}]", var, index);
+    state.count_statement()
+}
 
-                for idx in indexes {
-                    repr.push_str(&format!("[{}]", idx));
+// Runs the statements of a block in a new scope, stopping early if control leaves the block. If
+// `var` is given, it's defined in the scope first, e.g. the variable of a `for` loop.
+fn eval_block(block: &[Statement], var: Option<(&str, Value)>, state: &mut State,
+              console: &Console) -> Result<Option<Flow>> {
+    try!(count_statement(state, console));
+    state.enter_block();
 
-                    array_vec = match array_vec[index_int as usize] {
-                        Value::Array(ref vec) => vec.clone(),
-                        ref val => return Error::type_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense", repr, val.type_string_with_article(), self)),
-                    };
+    if let Some((name, val)) = var {
+        state.define_var(name, val);
+    }
 
-                    let index_val = try!(idx.eval(state, stream_opt.clone()));
+    let mut result = Ok(None);
 
-                    index_int = match index_val {
-                        Value::Int(i) => i,
-                        _ => return Error::type_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                    };
+    for stmt in block {
+        result = stmt.eval(state, console);
 
-                    if index_int < 0 {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense",
-                            index, index_int, self))
-                    }
+        match result {
+            Ok(None) => (),
+            _ => break,
+        }
+    }
 
-                    if index_int as usize >= array_vec.len() {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                            var, array_vec.len(), self))
-                    }
-                }
+    state.exit_block();
+    result
+}
 
-                let exp_val = try!(exp.eval(state, stream_opt));
-                array_vec[index_int as usize] = exp_val;
-                state.assign(var, Value::Array(array_vec)).map(|_| None)
+// Calls the function that an expression evaluates to. A name is looked up with
+// `State::lookup_callable` so that calling an undefined function has a clearer error, unless it's
+// the name of a builtin that hasn't been replaced.
+fn call(func: &Expr, args: &[Expr], source: &Display, state: &mut State,
+        console: &Console) -> Result<Option<Value>> {
+    if let ExprKind::Var(ref name) = func.kind {
+        if builtins::is_builtin(name) && state.lookup(name).is_none() && state.lookup_func(name).is_none() {
+            let mut arg_values = Vec::new();
+
+            for arg in args {
+                arg_values.push(try!(arg.eval(state, console)));
             }
-            Statement::Delete(ref var, ref index, ref indexes) => {
-                let mut array_vec = match state.lookup(var) {
-                    Some(&Value::Array(ref vec)) => vec.clone(),
-                    Some(ref val) => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, val.type_string_with_article(), self)),
-                    None => return Error::undef_var_error(
-                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
-                };
 
-                let index_val = try!(index.eval(state, stream_opt.clone()));
-                let mut index_int = match index_val {
-                    Value::Int(i) => i,
-                    _ => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                };
+            return builtins::call(name, source, arg_values, state, console).map(Some);
+        }
+    }
 
-                if index_int < 0 {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense",
-                        index, index_int, self))
-                }
+    let func_val = match func.kind {
+        ExprKind::Var(ref name) => try!(state.lookup_callable(name).map_err(|e| e.with_span(func.span))),
+        _ => match try!(func.eval(state, console)) {
+            Value::Function(f) => f,
+            val => return Error::type_error(
+                &format!("`{}` is {}, so it can't be called", func, val.type_string_with_article())),
+        },
+    };
 
-                if index_int as usize >= array_vec.len() {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                        var, array_vec.len(), self))
-                }
+    let mut arg_values = Vec::new();
 
-                let mut repr = format!("{}[{}]", var, index);
-                let mut curr = &mut array_vec as *mut Vec<Value>;
+    for arg in args {
+        arg_values.push(try!(arg.eval(state, console)));
+    }
 
-                for idx in indexes {
-                    repr.push_str(&format!("[{}]", idx));
+    state.call(func_val, arg_values, console)
+}
 
-                    unsafe {
-                        curr = match (*curr)[index_int as usize] {
-                            Value::Array(ref mut vec) => vec as *mut Vec<Value>,
-                            ref val => return Error::type_error(
-                                &format!("`{}` is {}, so `{}` doesn't make sense", repr, val.type_string_with_article(), self)),
-                        };
-                    }
+impl Statement {
+    pub fn eval(&self, state: &mut State, console: &Console) -> Result<Option<Flow>> {
+        count_statement(state, console)
+            .and_then(|_| self.eval_kind(state, console))
+            .map_err(|e| e.with_span(self.span))
+    }
 
-                    let index_val = try!(idx.eval(state, stream_opt.clone()));
+    fn eval_kind(&self, state: &mut State, console: &Console) -> Result<Option<Flow>> {
+        match self.kind {
+            StmtKind::ArrayElemAssign(ref var, ref index, ref indexes, ref exp) => {
+                let indexes = try!(eval_indexes(index, indexes, state, console));
+                let exp_val = try!(exp.eval(state, console));
 
-                    index_int = match index_val {
-                        Value::Int(i) => i,
-                        _ => return Error::type_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                    };
+                let mut root = match state.lookup(var) {
+                    Some(val) => val.clone(),
+                    None => return Error::undef_var_error(
+                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
+                };
 
-                    if index_int < 0 {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense",
-                            index, index_int, self))
-                    }
+                {
+                    let (last, path) = indexes.split_last().unwrap();
+                    let (container, repr) = try!(index::get_path_mut(&mut root, var, path, self));
+                    try!(index::set(container, last, exp_val, &repr, self));
+                    try!(state.check_len(container, &repr));
+                }
 
-                    let curr_len = unsafe { (*curr).len() };
+                state.assign(var, root).map(|_| None)
+            }
+            StmtKind::Delete(ref var, ref index, ref indexes) => {
+                let indexes = try!(eval_indexes(index, indexes, state, console));
 
-                    if index_int as usize >= curr_len {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                            var, array_vec.len(), self))
-                    }
+                let mut root = match state.lookup(var) {
+                    Some(val) => val.clone(),
+                    None => return Error::undef_var_error(
+                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
+                };
 
+                {
+                    let (last, path) = indexes.split_last().unwrap();
+                    let (container, repr) = try!(index::get_path_mut(&mut root, var, path, self));
+                    try!(index::remove(container, last, &repr, self));
                 }
 
-                unsafe {
-                    (*curr).remove(index_int as usize);
-                }
-                try!(state.assign(var, Value::Array(array_vec)));
-                Ok(None)
+                state.assign(var, root).map(|_| None)
             }
-            Statement::Defun(ref t, ref name, ref params, ref body) =>
+            StmtKind::Break(ref label) => Ok(Some(Flow::Break(label.clone()))),
+            StmtKind::Continue(ref label) => Ok(Some(Flow::Continue(label.clone()))),
+            StmtKind::Defun(ref t, ref name, ref params, ref body) =>
                 state.define_func(t, name, params, body).map(|_| None),
-            Statement::For(ref var, ref exp, ref block) => {
-                let val = try!(exp.eval(state, stream_opt.clone()));
+            // Programs with syntax errors are never run, so there's nothing to do here.
+            StmtKind::Error => Ok(None),
+            StmtKind::For(ref label, ref var, ref exp, ref block) => {
+                let val = try!(exp.eval(state, console));
+                // Maps are iterated over by their keys.
                 let vec = match val {
                     Value::Array(vec) => vec,
+                    Value::Map(map) => map.into_iter().map(|(k, _)| Value::Str(k)).collect(),
                     _ => return Error::type_error(
                         &format!("`{}` is {}, so `for {} in {} ...` doesn't make sense", exp, val.type_string_with_article(), var, exp)),
                 };
 
                 for array_val in vec {
-                    state.define_var(var, array_val);
-
-                    for stmt in block {
-                        try!(stmt.eval(state, stream_opt.clone()));
+                    match try!(eval_block(block, Some((&var[..], array_val)), state, console)) {
+                        Some(Flow::Break(ref l)) if targets_loop(l, label) => break,
+                        Some(Flow::Continue(ref l)) if targets_loop(l, label) => continue,
+                        flow @ Some(_) => return Ok(flow),
+                        None => (),
                     }
                 }
 
                 Ok(None)
             }
-            Statement::If(ref exp, ref block1, ref block2) => {
-                let val = try!(exp.eval(state, stream_opt.clone()));
+            StmtKind::If(ref exp, ref block1, ref block2) => {
+                let val = try!(exp.eval(state, console));
                 let block = match val {
                     Value::Bool(true) => block1,
                     Value::Bool(false) => block2,
@@ -182,51 +203,31 @@ impl Statement {
                         &format!("`{}` is {}, so `if ({}) ...` doesn't make sense", exp, val.type_string_with_article(), exp)),
                 };
 
-                for stmt in block.iter() {
-                    if let v @ Some(_) = try!(stmt.eval(state, stream_opt.clone())) {
-                        return Ok(v);
-                    }
-                }
-
+                eval_block(block, None, state, console)
+            }
+            StmtKind::Let(ref var, ref exp) => {
+                let val = try!(exp.eval(state, console));
+                state.declare_var(var, val).map(|_| None)
+            }
+            StmtKind::Print(ref exp) => {
+                let val = try!(exp.eval(state, console));
+                console.write_output(&format!("{}", val));
                 Ok(None)
             }
-            Statement::Let(ref var, ref exp) => {
-                let val = try!(exp.eval(state, stream_opt));
-                state.define_var(var, val);
+            StmtKind::PrintLine(ref exp) => {
+                let val = try!(exp.eval(state, console));
+                console.write_output(&format!("{}\n", val));
                 Ok(None)
             }
-            Statement::Print(ref exp) => match stream_opt.clone() {
-                Some(stream) => {
-                    stream.write_output(&format!("{}", try!(exp.eval(state, stream_opt))));
-                    Ok(None)
-                }
-                None => {
-                    print!("{}", try!(exp.eval(state, stream_opt)));
-                    io::stdout().flush().unwrap();
-                    Ok(None)
-                }
-            },
-            Statement::PrintLine(ref exp) => match stream_opt.clone() {
-                Some(stream) => {
-                    stream.write_output(&format!("{}\n", try!(exp.eval(state, stream_opt))));
-                    Ok(None)
-                }
-                None => {
-                    println!("{}", try!(exp.eval(state, stream_opt)));
-                    io::stdout().flush().unwrap();
-                    Ok(None)
-                }
-            },
-            Statement::Return(ref exp) => exp.eval(state, stream_opt).map(Some),
-            Statement::VarAssign(ref var, ref exp) => {
-                let val = try!(exp.eval(state, stream_opt));
+            StmtKind::Return(ref exp) => exp.eval(state, console).map(|val| Some(Flow::Return(val))),
+            StmtKind::VarAssign(ref var, ref exp) => {
+                let val = try!(exp.eval(state, console));
                 state.assign(var, val).map(|_| None)
             }
-            Statement::VoidCall(ref name, ref args) => state.call_function(name, args, stream_opt).map(|_| None),
-            Statement::While(ref exp, ref block) => {
-
+            StmtKind::VoidCall(ref func, ref args) => call(func, args, self, state, console).map(|_| None),
+            StmtKind::While(ref label, ref exp, ref block) => {
                 loop {
-                    let val = try!(exp.eval(state, stream_opt.clone()));
+                    let val = try!(exp.eval(state, console));
 
                     match val {
                         Value::Bool(true) => (),
@@ -235,10 +236,11 @@ impl Statement {
                             &format!("`{}` is {}, so `while ({}) ...` doesn't make sense", exp, val.type_string_with_article(), exp)),
                     };
 
-                    for stmt in block.iter() {
-                        if let v @ Some(_) = try!(stmt.eval(state, stream_opt.clone())) {
-                            return Ok(v);
-                        }
+                    match try!(eval_block(block, None, state, console)) {
+                        Some(Flow::Break(ref l)) if targets_loop(l, label) => return Ok(None),
+                        Some(Flow::Continue(ref l)) if targets_loop(l, label) => continue,
+                        flow @ Some(_) => return Ok(flow),
+                        None => (),
                     }
                 }
             }
@@ -247,97 +249,56 @@ impl Statement {
 }
 
 impl Expr {
-    pub fn eval(&self, state: &mut State, stream_opt: Option<Arc<Stream>>) -> Result<Value> {
-        match *self {
-            Expr::Array(ref vec) => {
+    pub fn eval(&self, state: &mut State, console: &Console) -> Result<Value> {
+        self.eval_kind(state, console)
+            .and_then(|val| state.check_len(&val, self).map(|_| val))
+            .map_err(|e| e.with_span(self.span))
+    }
+
+    fn eval_kind(&self, state: &mut State, console: &Console) -> Result<Value> {
+        match self.kind {
+            ExprKind::Array(ref vec) => {
                 let mut out = Vec::new();
 
                 for ref exp in vec {
-                    out.push(try!(exp.eval(state, stream_opt.clone())));
+                    out.push(try!(exp.eval(state, console)));
                 }
 
                 Ok(Value::Array(out))
             }
-            Expr::ArrayElement(ref var, ref index, ref indexes) => {
-                let mut array_vec = match state.lookup(var) {
-                    Some(&Value::Array(ref vec)) => vec.clone(),
-                    Some(ref val) => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, val.type_string_with_article(), self)),
-                    None => return Error::undef_var_error(
-                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
-                };
-
-                let index_val = try!(index.eval(state, stream_opt.clone()));
-                let mut index_int = match index_val {
-                    Value::Int(i) => i,
-                    _ => return Error::type_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                };
-
-                if index_int < 0 {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` is {}, so `{}` doesn't make sense",
-                        index, index_int, self))
-                }
-
-                if index_int as usize >= array_vec.len() {
-                    return Error::array_index_out_of_bounds_error(
-                        &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                        var, array_vec.len(), self))
-                }
-
-                let mut repr = format!("{}[{}]", var, index);
-
-                for idx in indexes {
-                    repr.push_str(&format!("[{}]", idx));
+            ExprKind::ArrayElement(ref var, ref index, ref indexes) => {
+                let indexes = try!(eval_indexes(index, indexes, state, console));
 
-                    array_vec = match array_vec[index_int as usize] {
-                        Value::Array(ref vec) => vec.clone(),
-                        ref val => return Error::type_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense", repr, val.type_string_with_article(), self)),
-                    };
-
-                    let index_val = try!(idx.eval(state, stream_opt.clone()));
-
-                    index_int = match index_val {
-                        Value::Int(i) => i,
-                        _ => return Error::type_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense", var, index_val.type_string_with_article(), self))
-                    };
-
-                    if index_int < 0 {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` is {}, so `{}` doesn't make sense",
-                            index, index_int, self))
-                    }
-
-                    if index_int as usize >= array_vec.len() {
-                        return Error::array_index_out_of_bounds_error(
-                            &format!("`{}` has {} elements in it, so `{}` doesn't make sense",
-                            var, array_vec.len(), self))
-                    }
+                match state.lookup(var) {
+                    Some(root) => index::get_path(root, var, &indexes, self).map(|val| val.clone()),
+                    None => Error::undef_var_error(
+                        &format!("The variable `{}` is not defined, so {} doesn't make sense", var, self)),
                 }
-
-                Ok(array_vec[index_int as usize].clone())
             }
-            Expr::BinExp(ref exp1, ref op, ref exp2) => {
-                let val1 = try!(exp1.eval(state, stream_opt.clone()));
-                let val2 = try!(exp2.eval(state, stream_opt));
+            ExprKind::BinExp(ref exp1, ref op, ref exp2) => {
+                let val1 = try!(exp1.eval(state, console));
+                let val2 = try!(exp2.eval(state, console));
+                let big_ints = state.config().big_ints;
 
                 match *op {
                     BinOp::And => bool_exp(self, val1, val2, |x, y| x && y),
                     BinOp::Or =>  bool_exp(self, val1, val2, |x, y| x || y),
                     BinOp::Equal => eq_exp(self, val1, val2, |x, y| x == y),
                     BinOp::NotEqual => eq_exp(self, val1, val2, |x, y| x != y),
-                    BinOp::GreaterOrEqual => ineq_exp(self, val1, val2, |x, y| x >= y),
-                    BinOp::GreaterThan => ineq_exp(self, val1, val2, |x, y| x > y),
-                    BinOp::LessOrEqual => ineq_exp(self, val1, val2, |x, y| x <= y),
-                    BinOp::LessThan => ineq_exp(self, val1, val2, |x, y| x < y),
-                    BinOp::Plus => arith_exp(self, val1, val2, |x, y| x + y),
-                    BinOp::Minus => arith_exp(self, val1, val2, |x, y| x - y),
-                    BinOp::Times => arith_exp(self, val1, val2, |x, y| x * y),
-                    BinOp::Divide => arith_exp(self, val1, val2, |x, y| x / y),
-                    BinOp::Modulus => arith_exp(self, val1, val2, |x, y| x % y),
+                    BinOp::GreaterOrEqual => ineq_exp(self, val1, val2, |o| o != Ordering::Less),
+                    BinOp::GreaterThan => ineq_exp(self, val1, val2, |o| o == Ordering::Greater),
+                    BinOp::LessOrEqual => ineq_exp(self, val1, val2, |o| o != Ordering::Greater),
+                    BinOp::LessThan => ineq_exp(self, val1, val2, |o| o == Ordering::Less),
+                    BinOp::Plus => arith_exp(self, val1, val2, big_ints,
+                        |x, y| x.checked_add(y), |x, y| x + y, |x, y| Some(x + y)),
+                    BinOp::Minus => arith_exp(self, val1, val2, big_ints,
+                        |x, y| x.checked_sub(y), |x, y| x - y, |x, y| Some(x - y)),
+                    BinOp::Times => arith_exp(self, val1, val2, big_ints,
+                        |x, y| x.checked_mul(y), |x, y| x * y, |x, y| Some(x * y)),
+                    BinOp::Divide => arith_exp(self, val1, val2, big_ints,
+                        |x, y| x.checked_div(y), |x, y| x / y, |x, y| if y.is_zero() { None } else { Some(x / y) }),
+                    BinOp::Modulus => arith_exp(self, val1, val2, big_ints,
+                        |x, y| x.checked_rem(y), |x, y| x % y, |x, y| if y.is_zero() { None } else { Some(x % y) }),
                     BinOp::Concat => match (val1, val2) {
                         (Value::Array(mut v1), Value::Array(v2)) => {
                             v1.extend(v2);
@@ -350,26 +311,48 @@ impl Expr {
                     },
                 }
             }
-            Expr::Call(ref name, ref args) => {
-                match state.call_function(name, args, stream_opt) {
-                    Ok(Some(val)) => Ok(val),
-                    Ok(None) => Error::type_error(
-                        &format!("The function {} doesn't return anything, so {} doesn't make sense", name, self)),
-                    Err(e) => Err(e),
+            ExprKind::Call(ref func, ref args) => {
+                match try!(call(func, args, self, state, console)) {
+                    Some(val) => Ok(val),
+                    None => Error::type_error(
+                        &format!("`{}` doesn't return anything, so `{}` doesn't make sense", func, self)),
+                }
+            }
+            ExprKind::Interpolation(ref parts) => {
+                let mut out = String::new();
+
+                for part in parts {
+                    out.push_str(&format!("{}", try!(part.eval(state, console))));
+                }
+
+                Ok(Value::Str(out))
+            }
+            ExprKind::Keys(ref exp) => {
+                let val = try!(exp.eval(state, console));
+
+                match val {
+                    Value::Map(ref map) => Ok(Value::Array(map.keys().map(|k| Value::Str(k.clone())).collect())),
+                    _ => Error::type_error(
+                        &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
                 }
             }
-            Expr::Length(ref exp) => {
-                let val = try!(exp.eval(state, stream_opt));
+            ExprKind::Lambda(ref return_type, ref params, ref body) => {
+                let func = Function::new(None, return_type, params, body, state.capture());
+                Ok(Value::Function(Arc::new(func)))
+            }
+            ExprKind::Length(ref exp) => {
+                let val = try!(exp.eval(state, console));
 
                 match val {
                     Value::Array(ref vec) => Ok(Value::Int(vec.len() as i64)),
+                    Value::Map(ref map) => Ok(Value::Int(map.len() as i64)),
                     Value::Str(ref string) => Ok(Value::Int(string.len() as i64)),
                     _ => Error::type_error(
                         &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
                 }
             }
-            Expr::Letters(ref exp) => {
-                let val = try!(exp.eval(state, stream_opt));
+            ExprKind::Letters(ref exp) => {
+                let val = try!(exp.eval(state, console));
 
                 match val {
                     Value::Str(ref string) => Ok(Value::Array(string.chars().map(|c| Value::Str(format!("{}", c))).collect())),
@@ -377,21 +360,39 @@ impl Expr {
                         &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
                 }
             }
-            Expr::Not(ref exp) => {
-                match try!(exp.eval(state, stream_opt)) {
+            ExprKind::Map(ref entries) => {
+                let mut map = BTreeMap::new();
+
+                for &(ref key, ref exp) in entries {
+                    let key_val = try!(key.eval(state, console));
+
+                    let key_string = match key_val {
+                        Value::Str(ref string) => string.clone(),
+                        _ => return Error::type_error(
+                            &format!("`{}` is {}, but map keys have to be strings, so `{}` doesn't make sense",
+                                     key, key_val.type_string_with_article(), self)),
+                    };
+
+                    map.insert(key_string, try!(exp.eval(state, console)));
+                }
+
+                Ok(Value::Map(map))
+            }
+            ExprKind::Not(ref exp) => {
+                match try!(exp.eval(state, console)) {
                     Value::Bool(b) => Ok(Value::Bool(!b)),
                     _ => Error::type_error(
                         &format!("`{}` is not a boolean, so `!{}` doesn't make sense", exp, exp)),
                 }
             }
-            Expr::Range(ref start, ref end) => {
-                let start_int = match try!(start.eval(state, stream_opt.clone())) {
+            ExprKind::Range(ref start, ref end) => {
+                let start_int = match try!(start.eval(state, console)) {
                     Value::Int(i) => i,
                     _ => return Error::type_error(
                         &format!("`{}` is not a int, so `{}` doesn't make sense", start, self)),
                 };
 
-                let end_int = match try!(end.eval(state, stream_opt)) {
+                let end_int = match try!(end.eval(state, console)) {
                     Value::Int(i) => i,
                     _ => return Error::type_error(
                         &format!("`{}` is not a int, so `{}` doesn't make sense", end, self)),
@@ -399,41 +400,49 @@ impl Expr {
 
                 // `stepper` ranges are not end-inclusive.
                 let (fixed_end, step_int) = if end_int >= start_int {
-                    (end_int + 1, 1)
+                    (end_int.checked_add(1), 1)
                 } else {
-                    (end_int - 1, -1)
+                    (end_int.checked_sub(1), -1)
                 };
 
+                let fixed_end = match fixed_end {
+                    Some(i) => i,
+                    None => return Error::overflow_error(
+                        &format!("`{}` ends at {}, which is too close to the limit of an int", self, end_int)),
+                };
+
+                try!(state.check_new_len(step_count(start_int, end_int, step_int), "elements", self));
+
                 let vec: Vec<_> = step!(start_int => fixed_end; step_int).into_iter().map(Value::Int).collect();
                 Ok(Value::Array(vec))
             }
-            Expr::ReadLine => match stream_opt.clone() {
-                Some(stream) => Ok(Value::Str(stream.read_input())),
-                None => {
-                    let mut buf = String::new();
-                    io::stdin().read_line(&mut buf).unwrap();
-
-                    if let Some('\n') = buf.chars().last() {
-                        let _ = buf.pop();
-                    }
-
-                    Ok(Value::Str(buf))
+            ExprKind::ReadLine => {
+                let waiting = Instant::now();
+                let line = console.read_line();
+                state.exclude_wait(waiting.elapsed());
+
+                // Cancelling the program stops it waiting for input, and the rest of the statement
+                // shouldn't run with the empty line it gets instead.
+                if console.is_cancelled() {
+                    return Error::cancelled_error("The program was cancelled");
                 }
-            },
-            Expr::Step(ref start, ref end, ref step) => {
-                let start_int = match try!(start.eval(state, stream_opt.clone())) {
+
+                Ok(Value::Str(line))
+            }
+            ExprKind::Step(ref start, ref end, ref step) => {
+                let start_int = match try!(start.eval(state, console)) {
                     Value::Int(i) => i,
                     _ => return Error::type_error(
                         &format!("`{}` is not a int, so `{}` doesn't make sense", start, self)),
                 };
 
-                let end_int = match try!(end.eval(state, stream_opt.clone())) {
+                let end_int = match try!(end.eval(state, console)) {
                     Value::Int(i) => i,
                     _ => return Error::type_error(
                         &format!("`{}` is not a int, so `{}` doesn't make sense", end, self)),
                 };
 
-                let step_int = match try!(step.eval(state, stream_opt)) {
+                let step_int = match try!(step.eval(state, console)) {
                     Value::Int(i) => i,
                     _ => return Error::type_error(
                         &format!("`{}` is not a int, so `{}` doesn't make sense", step, self)),
@@ -457,18 +466,75 @@ impl Expr {
                             start, start_int, end, end_int, step, step_int));
                 }
 
+                try!(state.check_new_len(step_count(start_int, end_int, step_int), "elements", self));
+
                 // `stepper` ranges are not end-inclusive.
-                let fixed_end = end_int + step_int.signum();
+                let fixed_end = match end_int.checked_add(step_int.signum()) {
+                    Some(i) => i,
+                    None => return Error::overflow_error(
+                        &format!("`{}` ends at {}, which is too close to the limit of an int", self, end_int)),
+                };
 
                 let vec: Vec<_> = step!(start_int => fixed_end; step_int).into_iter().map(Value::Int).collect();
                 Ok(Value::Array(vec))
             }
-            Expr::Value(ref val) => Ok(val.clone()),
-            Expr::Var(ref var) => {
+            ExprKind::ToFloat(ref exp) => {
+                let val = try!(exp.eval(state, console));
+
+                match val {
+                    Value::BigInt(ref b) => Ok(Value::Float(b.to_f64().unwrap_or(if b.is_negative() { f64::NEG_INFINITY } else { f64::INFINITY }))),
+                    Value::Float(f) => Ok(Value::Float(f)),
+                    Value::Int(i) => Ok(Value::Float(i as f64)),
+                    Value::Str(ref string) => match string.trim().parse() {
+                        Ok(f) => Ok(Value::Float(f)),
+                        Err(_) => Error::conversion_error(
+                            &format!("`{}` is \"{}\", which isn't a number, so {} doesn't make sense", exp, string, self)),
+                    },
+                    _ => Error::type_error(
+                        &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
+                }
+            }
+            ExprKind::ToInt(ref exp) => {
+                let val = try!(exp.eval(state, console));
+
+                match val {
+                    Value::BigInt(_) | Value::Int(_) => Ok(val.clone()),
+                    // Round towards zero, as long as the result fits in an int.
+                    Value::Float(f) if f.is_finite() && f.trunc() >= i64::MIN as f64 && f.trunc() < i64::MAX as f64 =>
+                        Ok(Value::Int(f.trunc() as i64)),
+                    Value::Float(f) => Error::conversion_error(
+                        &format!("`{}` is {:?}, which is too big to be an int, so {} doesn't make sense", exp, f, self)),
+                    Value::Str(ref string) => match string.trim().parse() {
+                        Ok(i) => Ok(Value::Int(i)),
+                        Err(_) => Error::conversion_error(
+                            &format!("`{}` is \"{}\", which isn't an int, so {} doesn't make sense", exp, string, self)),
+                    },
+                    _ => Error::type_error(
+                        &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
+                }
+            }
+            ExprKind::Value(ref val) => Ok(val.clone()),
+            ExprKind::Values(ref exp) => {
+                let val = try!(exp.eval(state, console));
+
+                match val {
+                    Value::Map(ref map) => Ok(Value::Array(map.values().cloned().collect())),
+                    _ => Error::type_error(
+                        &format!("{} is {}, so {} doesn't make sense", exp, val.type_string_with_article(), self))
+                }
+            }
+            ExprKind::Var(ref var) => {
+                // A name that isn't a variable can still refer to a function defined with it.
                 match state.lookup(var) {
                     Some(val) => Ok(val.clone()),
-                    None => Error::undef_var_error(
-                        &format!("The variable `{}` is not defined, so it can't be used in an expression", var))
+                    None => match state.lookup_func(var) {
+                        Some(func) => Ok(Value::Function(func)),
+                        // Builtins that haven't been replaced can be used as values too, e.g. to pass
+                        // `reverse` to `map`.
+                        None if builtins::is_builtin(var) => Ok(Value::Function(Arc::new(Function::builtin(var)))),
+                        None => Error::undef_var_error(
+                            &format!("The variable `{}` is not defined, so it can't be used in an expression", var))
+                    },
                 }
             }
         }
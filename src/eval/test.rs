@@ -1,7 +1,12 @@
 use ast::*;
 use ast::BinOp::*;
-use ast::Statement::*;
-use error::ErrorType;
+use std::thread;
+use std::time::Duration;
+
+use config::{Config, Limits};
+use console::{Buffer, Console, Stdio};
+use error::{ErrorType, PalError};
+use parser::{parse_program, parse_stmt};
 use state::State;
 
 #[test]
@@ -17,9 +22,9 @@ fn arith() {
     let stmt3 = stmt_var_assign!(x, bin_exp!(var!(x), Times, var!(y)));
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Int(-36), *state.lookup("x").unwrap());
@@ -39,9 +44,9 @@ fn bool() {
     let stmt3 = stmt_var_assign!(x, bin_exp!(boolean!(true), Or, var!(y)));
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -54,31 +59,31 @@ fn array_element_access() {
     let stmt = stmt_let!(array, array![int!(1), boolean!(false), array![string!("hello!")]]);
 
     let mut state = State::new();
-    stmt.eval(&mut state).unwrap();
+    stmt.eval(&mut state, &Stdio).unwrap();
 
     let index_neg_one = index!(array[int!(-1)]);
-    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_neg_one.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_neg_one.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 
     let index_zero = index!(array[int!(0)]);
-    assert_eq!(Value::Int(1), index_zero.eval(&mut state).unwrap());
+    assert_eq!(Value::Int(1), index_zero.eval(&mut state, &Stdio).unwrap());
 
     let index_one = index!(array[int!(1)]);
-    assert_eq!(Value::Bool(false), index_one.eval(&mut state).unwrap());
+    assert_eq!(Value::Bool(false), index_one.eval(&mut state, &Stdio).unwrap());
 
     let index_one_zero = index!(array[int!(1)][int!(0)]);
-    assert_eq!(Err(ErrorType::Type), index_one_zero.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), index_one_zero.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 
     let index_two_neg_one = index!(array[int!(2)][int!(-1)]);
-    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_two_neg_one.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_two_neg_one.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 
     let index_two_zero = index!(array[int!(2)][int!(0)]);
-    assert_eq!(Value::Str(String::from("hello!")), index_two_zero.eval(&mut state).unwrap());
+    assert_eq!(Value::Str(String::from("hello!")), index_two_zero.eval(&mut state, &Stdio).unwrap());
 
     let index_two_one = index!(array[int!(2)][int!(1)]);
-    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_two_one.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_two_one.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 
     let index_three = index!(array[int!(3)]);
-    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_three.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::ArrayIndexOutOfBounds), index_three.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -91,8 +96,8 @@ fn length_array() {
 
     let mut state = State::new();
 
-    assert_eq!(Value::Int(3), length1.eval(&mut state).unwrap());
-    assert_eq!(Value::Int(0), length2.eval(&mut state).unwrap());
+    assert_eq!(Value::Int(3), length1.eval(&mut state, &Stdio).unwrap());
+    assert_eq!(Value::Int(0), length2.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -101,7 +106,7 @@ fn length_string() {
 
     let mut state = State::new();
 
-    assert_eq!(Value::Int(6), length.eval(&mut state).unwrap());
+    assert_eq!(Value::Int(6), length.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -111,8 +116,8 @@ fn length_invalid_arg() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Type), bool_length.eval(&mut state).map_err(|e| e.err_type()));
-    assert_eq!(Err(ErrorType::Type), int_length.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), bool_length.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), int_length.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -131,8 +136,8 @@ fn letters_string() {
 
     let mut state = State::new();
 
-    assert_eq!(array1, length1.eval(&mut state).unwrap());
-    assert_eq!(array2, length2.eval(&mut state).unwrap());
+    assert_eq!(array1, length1.eval(&mut state, &Stdio).unwrap());
+    assert_eq!(array2, length2.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -143,9 +148,9 @@ fn letters_invalid_arg() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Type), array_letters.eval(&mut state).map_err(|e| e.err_type()));
-    assert_eq!(Err(ErrorType::Type), bool_letters.eval(&mut state).map_err(|e| e.err_type()));
-    assert_eq!(Err(ErrorType::Type), int_letters.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), array_letters.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), bool_letters.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), int_letters.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -155,7 +160,7 @@ fn empty_range() {
 
     let mut state = State::new();
 
-    assert_eq!(array, empty_range.eval(&mut state).unwrap());
+    assert_eq!(array, empty_range.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -165,7 +170,7 @@ fn range_down() {
 
     let mut state = State::new();
 
-    assert_eq!(array, range_down.eval(&mut state).unwrap());
+    assert_eq!(array, range_down.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -175,7 +180,7 @@ fn range_up() {
 
     let mut state = State::new();
 
-    assert_eq!(array, range_up.eval(&mut state).unwrap());
+    assert_eq!(array, range_up.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -186,9 +191,9 @@ fn range_invalid_args() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Type), invalid_arg1.eval(&mut state).map_err(|e| e.err_type()));
-    assert_eq!(Err(ErrorType::Type), invalid_arg2.eval(&mut state).map_err(|e| e.err_type()));
-    assert_eq!(Err(ErrorType::Type), invalid_args.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), invalid_arg1.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), invalid_arg2.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Type), invalid_args.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -198,7 +203,7 @@ fn step_up() {
 
     let mut state = State::new();
 
-    assert_eq!(array, step.eval(&mut state).unwrap());
+    assert_eq!(array, step.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -208,7 +213,7 @@ fn step_down() {
 
     let mut state = State::new();
 
-    assert_eq!(array, step.eval(&mut state).unwrap());
+    assert_eq!(array, step.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -218,7 +223,7 @@ fn step_down_miss() {
 
     let mut state = State::new();
 
-    assert_eq!(array, step.eval(&mut state).unwrap());
+    assert_eq!(array, step.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -228,7 +233,7 @@ fn step_up_miss() {
 
     let mut state = State::new();
 
-    assert_eq!(array, step.eval(&mut state).unwrap());
+    assert_eq!(array, step.eval(&mut state, &Stdio).unwrap());
 }
 
 #[test]
@@ -237,7 +242,7 @@ fn step_invalid_decrement() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Step), step.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Step), step.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -246,7 +251,7 @@ fn step_invalid_increment() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Step), step.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Step), step.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -255,7 +260,7 @@ fn step_zero_increment() {
 
     let mut state = State::new();
 
-    assert_eq!(Err(ErrorType::Step), step.eval(&mut state).map_err(|e| e.err_type()));
+    assert_eq!(Err(ErrorType::Step), step.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
 }
 
 #[test]
@@ -275,9 +280,9 @@ fn if_true() {
      });
 
      let mut state = State::new();
-     stmt1.eval(&mut state).unwrap();
-     stmt2.eval(&mut state).unwrap();
-     stmt3.eval(&mut state).unwrap();
+     stmt1.eval(&mut state, &Stdio).unwrap();
+     stmt2.eval(&mut state, &Stdio).unwrap();
+     stmt3.eval(&mut state, &Stdio).unwrap();
 
      assert_eq!(2, state.len());
      assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -301,9 +306,9 @@ fn if_false() {
      });
 
      let mut state = State::new();
-     stmt1.eval(&mut state).unwrap();
-     stmt2.eval(&mut state).unwrap();
-     stmt3.eval(&mut state).unwrap();
+     stmt1.eval(&mut state, &Stdio).unwrap();
+     stmt2.eval(&mut state, &Stdio).unwrap();
+     stmt3.eval(&mut state, &Stdio).unwrap();
 
      assert_eq!(2, state.len());
      assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -331,9 +336,9 @@ fn if_true_else() {
     });
 
      let mut state = State::new();
-     stmt1.eval(&mut state).unwrap();
-     stmt2.eval(&mut state).unwrap();
-     stmt3.eval(&mut state).unwrap();
+     stmt1.eval(&mut state, &Stdio).unwrap();
+     stmt2.eval(&mut state, &Stdio).unwrap();
+     stmt3.eval(&mut state, &Stdio).unwrap();
 
      assert_eq!(2, state.len());
      assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -361,9 +366,9 @@ fn if_false_else() {
     });
 
      let mut state = State::new();
-     stmt1.eval(&mut state).unwrap();
-     stmt2.eval(&mut state).unwrap();
-     stmt3.eval(&mut state).unwrap();
+     stmt1.eval(&mut state, &Stdio).unwrap();
+     stmt2.eval(&mut state, &Stdio).unwrap();
+     stmt3.eval(&mut state, &Stdio).unwrap();
 
      assert_eq!(2, state.len());
      assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -395,9 +400,9 @@ fn if_true_else_if2() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -429,9 +434,9 @@ fn if_false_else_if_true_else_if() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -464,9 +469,9 @@ fn if_false_else_if_false_else_if_true() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -502,9 +507,9 @@ fn if_true_else_if2_else() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -540,9 +545,9 @@ fn if_false_else_if2_true_else() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(true), *state.lookup("x").unwrap());
@@ -578,9 +583,9 @@ fn if_false_else_if2_false_else_true() {
     });
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
-    stmt3.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
+    stmt3.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(2, state.len());
     assert_eq!(Value::Bool(false), *state.lookup("x").unwrap());
@@ -609,7 +614,7 @@ fn complex() {
     let let_total = stmt_let!(total, int!(0));
 
     let sum3 = stmt_defun!(Type::Int, sum3(x, y, z) {
-        Return(bin_exp!(var!(x), Plus, bin_exp!(var!(y), Plus, var!(z))))
+        stmt_return!(bin_exp!(var!(x), Plus, bin_exp!(var!(y), Plus, var!(z))))
     });
 
     let range = stmt_defun!(Type::Void, range(i) {
@@ -622,10 +627,10 @@ fn complex() {
     let range_call = stmt_void_call!(range(int!(10)));
 
     let mut state = State::new();
-    let_total.eval(&mut state).unwrap();
-    sum3.eval(&mut state).unwrap();
-    range.eval(&mut state).unwrap();
-    range_call.eval(&mut state).unwrap();
+    let_total.eval(&mut state, &Stdio).unwrap();
+    sum3.eval(&mut state, &Stdio).unwrap();
+    range.eval(&mut state, &Stdio).unwrap();
+    range_call.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(1, state.len());
     assert_eq!(Value::Int(198), *state.lookup("total").unwrap());
@@ -637,9 +642,613 @@ fn array_delete_first() {
     let stmt2 = stmt_delete!(x[int!(0)]);
 
     let mut state = State::new();
-    stmt1.eval(&mut state).unwrap();
-    stmt2.eval(&mut state).unwrap();
+    stmt1.eval(&mut state, &Stdio).unwrap();
+    stmt2.eval(&mut state, &Stdio).unwrap();
 
     assert_eq!(1, state.len());
     assert_eq!(Value::Array(vec![val_int!(2), val_int!(3)]), *state.lookup("x").unwrap());
 }
+
+#[test]
+fn error_location() {
+    let text = "let x = 1;\nlet y = x + true;\n";
+    let program = parse_program(text).0;
+
+    let mut state = State::new();
+    program[0].eval(&mut state, &Stdio).unwrap();
+
+    let err = program[1].eval(&mut state, &Stdio).unwrap_err().locate("test.pal", text);
+    assert_eq!(ErrorType::Type, err.err_type());
+    assert!(format!("{}", err).starts_with("test.pal:2:9: TypeError: "));
+}
+
+#[test]
+fn string_interpolation() {
+    let text = "let name = \"pal\";\nlet s = \"hello ${name}, ${1 + 2}${[true]}!\";\n";
+    let program = parse_program(text).0;
+
+    let mut state = State::new();
+
+    for stmt in program {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(val_string!("hello pal, 3[true]!"), *state.lookup("s").unwrap());
+}
+
+#[test]
+fn float_arith() {
+    let mut state = State::new();
+
+    let sum = bin_exp!(float!(1.5), Plus, float!(2.25));
+    assert_eq!(val_float!(3.75), sum.eval(&mut state, &Stdio).unwrap());
+
+    let mixed = bin_exp!(int!(3), Divide, float!(2.0));
+    assert_eq!(val_float!(1.5), mixed.eval(&mut state, &Stdio).unwrap());
+
+    let int_div = bin_exp!(int!(3), Divide, int!(2));
+    assert_eq!(val_int!(1), int_div.eval(&mut state, &Stdio).unwrap());
+
+    let cmp = bin_exp!(int!(1), LessThan, float!(1.5));
+    assert_eq!(Value::Bool(true), cmp.eval(&mut state, &Stdio).unwrap());
+
+    let eq = bin_exp!(int!(2), Equal, float!(2.0));
+    assert_eq!(Value::Bool(true), eq.eval(&mut state, &Stdio).unwrap());
+
+    let invalid = bin_exp!(float!(1.0), Plus, boolean!(true));
+    assert_eq!(Err(ErrorType::Type), invalid.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+}
+
+#[test]
+fn float_conversions() {
+    let mut state = State::new();
+
+    let to_float = unspanned_exp!(ExprKind::ToFloat(Box::new(int!(3))));
+    assert_eq!(val_float!(3.0), to_float.eval(&mut state, &Stdio).unwrap());
+
+    let to_int = unspanned_exp!(ExprKind::ToInt(Box::new(float!(-2.75))));
+    assert_eq!(val_int!(-2), to_int.eval(&mut state, &Stdio).unwrap());
+
+    let from_string = unspanned_exp!(ExprKind::ToInt(Box::new(string!(" 42 "))));
+    assert_eq!(val_int!(42), from_string.eval(&mut state, &Stdio).unwrap());
+
+    let too_big = unspanned_exp!(ExprKind::ToInt(Box::new(float!(1e30))));
+    assert_eq!(Err(ErrorType::Conversion), too_big.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let not_a_number = unspanned_exp!(ExprKind::ToFloat(Box::new(string!("pal"))));
+    assert_eq!(Err(ErrorType::Conversion), not_a_number.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+}
+
+#[test]
+fn checked_int_arith() {
+    let mut state = State::new();
+
+    let div_zero = bin_exp!(int!(1), Divide, int!(0));
+    let error = div_zero.eval(&mut state, &Stdio).unwrap_err();
+    assert_eq!(ErrorType::DivisionByZero, error.err_type());
+    assert_eq!("`1 / 0` divides by zero", error.message());
+
+    let mod_zero = bin_exp!(int!(1), Modulus, int!(0));
+    assert_eq!(Err(ErrorType::DivisionByZero), mod_zero.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let overflow = bin_exp!(int!(i64::max_value()), Plus, int!(1));
+    assert_eq!(Err(ErrorType::Overflow), overflow.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let min_div = bin_exp!(int!(i64::min_value()), Divide, int!(-1));
+    assert_eq!(Err(ErrorType::Overflow), min_div.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let range_to_max = unspanned_exp!(ExprKind::Range(Box::new(int!(0)), Box::new(int!(i64::max_value()))));
+    assert_eq!(Err(ErrorType::Overflow), range_to_max.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let range_to_min = unspanned_exp!(ExprKind::Range(Box::new(int!(0)), Box::new(int!(i64::min_value()))));
+    assert_eq!(Err(ErrorType::Overflow), range_to_min.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let step_to_max = unspanned_exp!(ExprKind::Step(Box::new(int!(0)), Box::new(int!(i64::max_value())),
+                                                    Box::new(int!(1 << 62))));
+    assert_eq!(Err(ErrorType::Overflow), step_to_max.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let float_div_zero = bin_exp!(float!(1.0), Divide, int!(0));
+    assert_eq!(val_float!(::std::f64::INFINITY), float_div_zero.eval(&mut state, &Stdio).unwrap());
+}
+
+#[test]
+fn big_ints() {
+    let mut state = State::with_config(Config { big_ints: true, ..Config::default() });
+    let text = "let x = 9223372036854775807 + 1;\nlet y = x * x;\nlet z = y / x - x;";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!("9223372036854775808", format!("{}", state.lookup("x").unwrap()));
+    assert_eq!("85070591730234615865843651857942052864", format!("{}", state.lookup("y").unwrap()));
+    // Results that fit in an int again become regular ints.
+    assert_eq!(&val_int!(0), state.lookup("z").unwrap());
+
+    let cmp = bin_exp!(var!(x), GreaterThan, int!(i64::max_value()));
+    assert_eq!(Value::Bool(true), cmp.eval(&mut state, &Stdio).unwrap());
+}
+
+#[test]
+fn break_and_continue() {
+    let mut state = State::new();
+    let text = "let evens = [];\nlet i = 0;\nwhile (true) {\n    i = i + 1;\n    if (i > 6) { break; }\n    if (i % 2 == 1) { continue; }\n    evens = evens ++ [i];\n}\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(&val_array!(val_int!(2), val_int!(4), val_int!(6)), state.lookup("evens").unwrap());
+}
+
+#[test]
+fn labelled_break_and_continue() {
+    let mut state = State::new();
+    let text = "let pairs = 0;\nouter: for x in range(0, 5) {\n    for y in range(0, 5) {\n        if (y > x) { continue outer; }\n        if (x == 3) { break outer; }\n        pairs = pairs + 1;\n    }\n}\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    // (0, 0), (1, 0), (1, 1), (2, 0), (2, 1), (2, 2)
+    assert_eq!(&val_int!(6), state.lookup("pairs").unwrap());
+}
+
+#[test]
+fn return_from_loop() {
+    let mut state = State::new();
+    let text = "int first_even(a) {\n    for x in a {\n        if (x % 2 == 0) { return x; }\n    }\n    return 0 - 1;\n}\nlet x = first_even([1, 3, 4, 5]);\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(&val_int!(4), state.lookup("x").unwrap());
+}
+
+#[test]
+fn map_elements() {
+    let mut state = State::new();
+    let text = "let m = {\"a\": 1, \"b\": [2, 3]};\nm[\"c\"] = 4;\nm[\"b\"][0] = 5;\ndelete m[\"a\"];\nlet b = m[\"b\"];\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!("{\"b\": [5, 3], \"c\": 4}", format!("{}", state.lookup("m").unwrap()));
+    assert_eq!(&val_array!(val_int!(5), val_int!(3)), state.lookup("b").unwrap());
+
+    let missing = unspanned_exp!(ExprKind::ArrayElement(String::from("m"), Box::new(string!("a")), Vec::new()));
+    assert_eq!(Err(ErrorType::MissingKey), missing.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+
+    let int_key = unspanned_exp!(ExprKind::ArrayElement(String::from("m"), Box::new(int!(0)), Vec::new()));
+    assert_eq!(Err(ErrorType::Type), int_key.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+}
+
+#[test]
+fn map_builtins_and_iteration() {
+    let mut state = State::new();
+    let text = "let counts = {};\nfor word in [\"a\", \"b\", \"a\"] {\n    let seen = false;\n    for k in counts {\n        if (k == word) { seen = true; }\n    }\n    if (!seen) { counts[word] = 0; }\n    counts[word] = counts[word] + 1;\n}\nlet k = keys(counts);\nlet v = values(counts);\nlet n = length(counts);\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(&val_array!(val_string!("a"), val_string!("b")), state.lookup("k").unwrap());
+    assert_eq!(&val_array!(val_int!(2), val_int!(1)), state.lookup("v").unwrap());
+    assert_eq!(&val_int!(2), state.lookup("n").unwrap());
+}
+
+// Runs a program in a fresh state, returning the state afterwards and the error the program failed
+// with, if any.
+fn run(text: &str) -> (State, Option<ErrorType>) {
+    run_with_config(text, Config::default())
+}
+
+fn run_with_config(text: &str, config: Config) -> (State, Option<ErrorType>) {
+    let mut state = State::with_config(config);
+    let (program, errors) = parse_program(text);
+    assert!(errors.is_empty(), "syntax errors in {:?}", text);
+
+    for stmt in program {
+        if let Err(e) = stmt.eval(&mut state, &Stdio) {
+            return (state, Some(e.err_type()));
+        }
+    }
+
+    (state, None)
+}
+
+#[test]
+fn function_reads_globals() {
+    let (state, error) = run("let x = 1;\nint f() {\n    return x + 1;\n}\nlet y = f();\n");
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(2), state.lookup("y").unwrap());
+}
+
+#[test]
+fn function_assigns_globals() {
+    let (state, error) = run("let x = 1;\nvoid f() {\n    x = 2;\n}\nf();\n");
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(2), state.lookup("x").unwrap());
+}
+
+#[test]
+fn function_cannot_read_caller_locals() {
+    let text = "int g() {\n    return secret;\n}\nint f() {\n    let secret = 1;\n    return g();\n}\nlet x = f();\n";
+    let (_, error) = run(text);
+
+    assert_eq!(Some(ErrorType::UndefinedVariable), error);
+}
+
+#[test]
+fn function_cannot_assign_caller_locals() {
+    let text = "void g() {\n    y = 2;\n}\nint f() {\n    let y = 1;\n    g();\n    return y;\n}\nlet x = f();\n";
+    let (_, error) = run(text);
+
+    assert_eq!(Some(ErrorType::UndefinedVariable), error);
+}
+
+#[test]
+fn function_locals_shadow_globals() {
+    let text = "let x = 1;\nint f(x) {\n    x = x + 10;\n    return x;\n}\nlet y = f(5);\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(1), state.lookup("x").unwrap());
+    assert_eq!(&val_int!(15), state.lookup("y").unwrap());
+}
+
+#[test]
+fn function_locals_do_not_leak() {
+    let text = "void f() {\n    let local = 1;\n}\nf();\nlet x = local;\n";
+    let (state, error) = run(text);
+
+    assert_eq!(Some(ErrorType::UndefinedVariable), error);
+    assert_eq!(None, state.lookup("local"));
+}
+
+#[test]
+fn recursive_calls_have_separate_locals() {
+    let text = "int fact(n) {\n    if (n == 0) { return 1; }\n    let m = n;\n    let rest = fact(n - 1);\n    return m * rest;\n}\nlet x = fact(5);\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(120), state.lookup("x").unwrap());
+}
+
+#[test]
+fn block_locals_do_not_leak() {
+    let (state, error) = run("if (true) {\n    let inner = 1;\n}\nlet x = inner;\n");
+
+    assert_eq!(Some(ErrorType::UndefinedVariable), error);
+    assert_eq!(None, state.lookup("inner"));
+}
+
+#[test]
+fn loop_variable_does_not_outlive_loop() {
+    let (state, error) = run("for i in [1, 2, 3] {\n    let j = i;\n}\n");
+
+    assert_eq!(None, error);
+    assert_eq!(None, state.lookup("i"));
+    assert_eq!(None, state.lookup("j"));
+}
+
+#[test]
+fn let_shadows_outer_variable() {
+    let text = "let x = 1;\nlet seen = 0;\nwhile (seen == 0) {\n    let x = 2;\n    seen = x;\n}\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(1), state.lookup("x").unwrap());
+    assert_eq!(&val_int!(2), state.lookup("seen").unwrap());
+}
+
+#[test]
+fn assignment_updates_outer_variable() {
+    let (state, error) = run("let total = 0;\nfor i in [1, 2, 3] {\n    total = total + i;\n}\n");
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(6), state.lookup("total").unwrap());
+}
+
+#[test]
+fn let_redeclared_in_same_block() {
+    let (_, error) = run("if (true) {\n    let x = 1;\n    let x = 2;\n}\n");
+    assert_eq!(Some(ErrorType::RedefinedVariable), error);
+
+    let (_, error) = run("void f() {\n    let x = 1;\n    let x = 2;\n}\nf();\n");
+    assert_eq!(Some(ErrorType::RedefinedVariable), error);
+}
+
+#[test]
+fn let_redefined_at_top_level() {
+    let (state, error) = run("let x = 1;\nlet x = x + 1;\n");
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(2), state.lookup("x").unwrap());
+
+    // Each line of the REPL is parsed and run on its own in the same state.
+    let mut state = State::new();
+
+    for line in &["let y = 1;", "let y = 2;"] {
+        parse_stmt(line).0.unwrap().eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(&val_int!(2), state.lookup("y").unwrap());
+}
+
+#[test]
+fn block_scope_exited_after_error() {
+    let mut state = State::new();
+    let failing = parse_program("if (true) {\n    let x = 1;\n    let y = x + true;\n}\n").0;
+    assert!(failing[0].eval(&mut state, &Stdio).is_err());
+
+    // The scope of the failed block shouldn't still be around, so `x` can be declared globally.
+    let stmt = stmt_let!(x, int!(2));
+    stmt.eval(&mut state, &Stdio).unwrap();
+    assert_eq!(1, state.len());
+}
+
+#[test]
+fn functions_as_values() {
+    let text = "int double(x) {\n    return x * 2;\n}\nint apply(f, x) {\n    return f(x);\n}\nlet g = double;\nlet a = g(1);\nlet b = apply(double, 2);\nlet fs = [double, int (x) { return x + 1; }];\nlet c = fs[1](fs[0](3));\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(2), state.lookup("a").unwrap());
+    assert_eq!(&val_int!(4), state.lookup("b").unwrap());
+    assert_eq!(&val_int!(7), state.lookup("c").unwrap());
+}
+
+#[test]
+fn closures_capture_locals() {
+    let text = "function adder(n) {\n    return int (x) { return x + n; };\n}\nlet add2 = adder(2);\nlet x = add2(5);\nlet y = adder(10)(5);\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(7), state.lookup("x").unwrap());
+    assert_eq!(&val_int!(15), state.lookup("y").unwrap());
+}
+
+#[test]
+fn closures_see_globals() {
+    let text = "let count = 0;\nlet inc = void () { count = count + 1; };\ninc();\ninc();\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(2), state.lookup("count").unwrap());
+}
+
+#[test]
+fn calling_non_function() {
+    let (_, error) = run("let x = 1;\nlet y = x(2);\n");
+    assert_eq!(Some(ErrorType::Type), error);
+
+    let (_, error) = run("let a = [1];\nlet y = a[0](2);\n");
+    assert_eq!(Some(ErrorType::Type), error);
+
+    let (_, error) = run("let y = nothing(2);\n");
+    assert_eq!(Some(ErrorType::UndefinedFunction), error);
+}
+
+#[test]
+fn function_return_type() {
+    let (_, error) = run("function f() {\n    return 1;\n}\nlet x = f();\n");
+    assert_eq!(Some(ErrorType::Type), error);
+
+    let (_, error) = run("let f = int (x) { return x; };\nlet y = f(1, 2);\n");
+    assert_eq!(Some(ErrorType::Argument), error);
+}
+
+#[test]
+fn array_map_filter_reduce() {
+    let text = "int square(x) {\n    return x * x;\n}\nboolean is_odd(x) {\n    return x % 2 == 1;\n}\nint add(a, b) {\n    return a + b;\n}\nlet xs = [1, 2, 3, 4];\nlet squares = map(xs, square);\nlet odds = filter(xs, is_odd);\nlet sum = reduce(xs, add, 0);\nlet doubled = map(xs, int (x) { return x * 2; });\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_array!(val_int!(1), val_int!(4), val_int!(9), val_int!(16)), state.lookup("squares").unwrap());
+    assert_eq!(&val_array!(val_int!(1), val_int!(3)), state.lookup("odds").unwrap());
+    assert_eq!(&val_int!(10), state.lookup("sum").unwrap());
+    assert_eq!(&val_array!(val_int!(2), val_int!(4), val_int!(6), val_int!(8)), state.lookup("doubled").unwrap());
+
+    // `map` can be bound and passed like the other builtins, and so can the builtins passed to it.
+    let text = "let f = map;\nlet ys = f([1, 2], int (x) { return x + 1; });\ndictionary counts(xs) { return {\"n\": length(xs)}; }\nlet c = counts(ys);\nlet rs = map([[1, 2]], reverse);\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_array!(val_int!(2), val_int!(3)), state.lookup("ys").unwrap());
+    assert!(match *state.lookup("c").unwrap() { Value::Map(ref m) => m.len() == 1, _ => false });
+    assert_eq!(&val_array!(val_array!(val_int!(2), val_int!(1))), state.lookup("rs").unwrap());
+}
+
+#[test]
+fn array_sort() {
+    let text = "int descending(a, b) {\n    return b - a;\n}\nlet xs = [3, 1, 2];\nlet up = sort(xs);\nlet down = sort(xs, descending);\nlet words = sort([\"pear\", \"apple\"]);\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&val_array!(val_int!(1), val_int!(2), val_int!(3)), state.lookup("up").unwrap());
+    assert_eq!(&val_array!(val_int!(3), val_int!(2), val_int!(1)), state.lookup("down").unwrap());
+    assert_eq!(&val_array!(val_string!("apple"), val_string!("pear")), state.lookup("words").unwrap());
+    // The original array is left alone.
+    assert_eq!(&val_array!(val_int!(3), val_int!(1), val_int!(2)), state.lookup("xs").unwrap());
+
+    let (_, error) = run("let xs = sort([1, \"a\"]);\n");
+    assert_eq!(Some(ErrorType::Type), error);
+
+    // Big ints are sorted with the other numbers.
+    let text = "let big = 9223372036854775807 + 1;\nlet xs = sort([big, 2.5, 9223372036854775807, 1]);\n";
+    let (state, error) = run_with_config(text, Config { big_ints: true, ..Config::default() });
+
+    assert_eq!(None, error);
+    assert_eq!(&val_array!(val_int!(1), val_float!(2.5), val_int!(i64::max_value()), state.lookup("big").unwrap().clone()),
+               state.lookup("xs").unwrap());
+}
+
+#[test]
+fn array_search_and_slice() {
+    let text = "let xs = [5, 6, 7];\nlet has = contains(xs, 6);\nlet missing = contains(xs, 8);\nlet i = index_of(xs, 7);\nlet j = index_of(xs, 8);\nlet rev = reverse(xs);\nlet part = slice(xs, 1, 3);\nlet joined = join(xs, \", \");\n";
+    let (state, error) = run(text);
+
+    assert_eq!(None, error);
+    assert_eq!(&Value::Bool(true), state.lookup("has").unwrap());
+    assert_eq!(&Value::Bool(false), state.lookup("missing").unwrap());
+    assert_eq!(&val_int!(2), state.lookup("i").unwrap());
+    assert_eq!(&val_int!(-1), state.lookup("j").unwrap());
+    assert_eq!(&val_array!(val_int!(7), val_int!(6), val_int!(5)), state.lookup("rev").unwrap());
+    assert_eq!(&val_array!(val_int!(6), val_int!(7)), state.lookup("part").unwrap());
+    assert_eq!(&val_string!("5, 6, 7"), state.lookup("joined").unwrap());
+
+    let (_, error) = run("let xs = slice([1, 2], 1, 3);\n");
+    assert_eq!(Some(ErrorType::ArrayIndexOutOfBounds), error);
+
+    let (_, error) = run("let xs = reverse([1], [2]);\n");
+    assert_eq!(Some(ErrorType::Argument), error);
+}
+
+#[test]
+fn builtins_can_be_replaced() {
+    let (state, error) = run("int reverse(x) {\n    return x;\n}\nlet y = reverse(1);\n");
+
+    assert_eq!(None, error);
+    assert_eq!(&val_int!(1), state.lookup("y").unwrap());
+}
+
+// Registers the native functions used by the native function tests.
+fn native_state() -> State {
+    let mut state = State::new();
+
+    state.register_native("triple", &[Type::Int], Type::Int, |args| match args[0] {
+        Value::Int(i) => Ok(Some(Value::Int(i * 3))),
+        _ => unreachable!(),
+    }).unwrap();
+
+    state.register_native("assert_eq", &[Type::Any, Type::Any], Type::Void, |args| {
+        if args[0] == args[1] {
+            Ok(None)
+        } else {
+            Err(format!("`{}` isn't equal to `{}`", args[0], args[1]))
+        }
+    }).unwrap();
+
+    state
+}
+
+#[test]
+fn native_functions() {
+    let mut state = native_state();
+    let text = "let x = triple(2);\nassert_eq(x, 6);\nlet xs = map([1, 2], triple);\n";
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &Stdio).unwrap();
+    }
+
+    assert_eq!(&val_int!(6), state.lookup("x").unwrap());
+    assert_eq!(&val_array!(val_int!(3), val_int!(6)), state.lookup("xs").unwrap());
+    assert_eq!(Some(val_int!(12)), state.call_function("triple", &[int!(4)], &Stdio).unwrap());
+}
+
+#[test]
+fn native_function_errors() {
+    let mut state = native_state();
+
+    let failed = state.call_function("assert_eq", &[int!(1), int!(2)], &Stdio).unwrap_err();
+    assert_eq!(ErrorType::Native, failed.err_type());
+    assert_eq!("`1` isn't equal to `2`", failed.message());
+
+    let wrong_type = state.call_function("triple", &[string!("a")], &Stdio);
+    assert_eq!(Err(ErrorType::Type), wrong_type.map_err(|e| e.err_type()));
+
+    let wrong_count = state.call_function("triple", &[], &Stdio);
+    assert_eq!(Err(ErrorType::Argument), wrong_count.map_err(|e| e.err_type()));
+
+    assert!(state.register_native("triple", &[], Type::Void, |_| Ok(None)).is_err());
+
+    let redefined = stmt_defun!(Type::Int, triple(x) { stmt_return!(var!(x)) });
+    assert_eq!(Err(ErrorType::RedefinedFunction), redefined.eval(&mut state, &Stdio).map_err(|e| e.err_type()));
+}
+
+#[test]
+fn console_io() {
+    let console = Buffer::with_input(&["Ada", "Lovelace"]);
+    let text = "let first = read_line();\nprint \"Hello, \";\nprint_line \"${first} ${read_line()}!\";\n\
+                print_line length(read_line());\n";
+
+    let mut state = State::new();
+
+    for stmt in parse_program(text).0 {
+        stmt.eval(&mut state, &console).unwrap();
+    }
+
+    assert_eq!("Hello, Ada Lovelace!\n0\n", console.output());
+    assert_eq!("", console.errors());
+}
+
+#[test]
+fn statement_limit() {
+    let config = Config { limits: Limits { max_statements: Some(100), ..Limits::default() }, ..Config::default() };
+
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("while (true) { }", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("let i = 0;\nwhile (i < 100) { i = i + 1; }", config.clone()).1);
+    assert_eq!(None, run_with_config("let i = 0;\nwhile (i < 10) { i = i + 1; }", config).1);
+}
+
+#[test]
+fn call_depth_limit() {
+    let config = Config { limits: Limits { max_call_depth: Some(20), ..Limits::default() }, ..Config::default() };
+    let text = "int count(n) { if (n == 0) { return 0; } return 1 + count(n - 1); }\n";
+
+    assert_eq!(None, run_with_config(&format!("{}let x = count(19);", text), config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config(&format!("{}let x = count(20);", text), config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("void f() { f(); }\nf();", config).1);
+}
+
+#[test]
+fn length_limit() {
+    let config = Config { limits: Limits { max_len: Some(10), ..Limits::default() }, ..Config::default() };
+
+    assert_eq!(None, run_with_config("let a = range(1, 10);\nlet s = \"0123456789\";", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("let a = range(1, 9223372036854775807);", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("let a = step(0, 1000, 10);", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("let a = range(1, 6) ++ range(1, 6);", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit),
+        run_with_config("let s = \"a\";\nwhile (true) { s = \"${s}${s}\"; }", config.clone()).1);
+    assert_eq!(Some(ErrorType::ResourceLimit),
+        run_with_config("let m = {};\nfor i in range(1, 10) { m[\"${i}\"] = i; }\nm[\"x\"] = 0;", config).1);
+}
+
+#[test]
+fn timeout() {
+    let config = Config { limits: Limits { timeout: Some(Duration::from_millis(10)), ..Limits::default() }, ..Config::default() };
+
+    assert_eq!(Some(ErrorType::ResourceLimit), run_with_config("while (true) { }", config).1);
+}
+
+// A console whose input takes a while to arrive, like a user answering a prompt.
+struct SlowInput;
+
+impl Console for SlowInput {
+    fn write_output(&self, _s: &str) {}
+
+    fn read_line(&self) -> String {
+        thread::sleep(Duration::from_millis(50));
+        String::from("1")
+    }
+
+    fn report_error(&self, _e: &PalError, _file: &str, _text: &str) {}
+}
+
+#[test]
+fn timeout_excludes_waiting_for_input() {
+    let limits = Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() };
+    let mut state = State::with_config(Config { limits: limits, ..Config::default() });
+
+    for stmt in parse_program("let a = read_line();\nlet b = read_line();\nlet c = \"${a}${b}\";\n").0 {
+        stmt.eval(&mut state, &SlowInput).unwrap();
+    }
+
+    assert_eq!(&val_string!("11"), state.lookup("c").unwrap());
+}
@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod test;
+
+use std::sync::Arc;
+
+use ast::{Type, Value};
+use config::Config;
+use console::{Console, Stdio};
+use error::{PalError, PalResult};
+use parser::parse_exp;
+use state::State;
+
+// Runs pal code on behalf of a host program. Variables and functions defined by one piece of code
+// are kept for the next, and the host can read and change them in between.
+pub struct Interpreter {
+    state: State,
+    // Where the output of `print` goes and where `read_line` reads from.
+    console: Arc<Console + Send + Sync>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Interpreter { state: State::with_config(config), console: Arc::new(Stdio) }
+    }
+
+    // Redirects the I/O of the code run from now on, e.g. to a `Buffer` so that the host can read
+    // what it prints.
+    pub fn set_console(&mut self, console: Arc<Console + Send + Sync>) {
+        self.console = console;
+    }
+
+    // Evaluates some pal code. If it's a single expression, e.g. `x + 1`, its value is returned;
+    // otherwise it's run as a program and `None` is returned.
+    pub fn eval_str(&mut self, text: &str) -> PalResult<Option<Value>> {
+        match parse_exp(text) {
+            Ok(exp) => {
+                self.state.start_run();
+                exp.eval(&mut self.state, &*self.console)
+                    .map(Some)
+                    .map_err(|e| PalError::from(e.locate("<program>", text)))
+            }
+            Err(_) => {
+                try!(::run_named_program("<program>", text, &mut self.state, &*self.console));
+                Ok(None)
+            }
+        }
+    }
+
+    // Returns the value of a variable, or `None` if it hasn't been defined.
+    pub fn get_var(&self, name: &str) -> Option<&Value> {
+        self.state.lookup(name)
+    }
+
+    // Sets the value of a variable, defining it as a global variable if it hasn't been defined yet.
+    pub fn set_var(&mut self, name: &str, val: Value) {
+        if self.state.assign(name, val.clone()).is_err() {
+            self.state.define_var(name, val);
+        }
+    }
+
+    // Calls a function with the given arguments, returning its return value if it has one. The name
+    // can be that of a function defined by pal code, a native function or a variable holding a
+    // function.
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> PalResult<Option<Value>> {
+        let func = try!(self.state.lookup_callable(name));
+        self.state.start_run();
+        self.state.call(func, args, &*self.console).map_err(PalError::from)
+    }
+
+    // Makes a Rust function callable from pal code. See `State::register_native`.
+    pub fn register_native<F>(&mut self, name: &str, params: &[Type], return_type: Type, func: F) -> PalResult<()>
+        where F: Fn(Vec<Value>) -> Result<Option<Value>, String> + Send + Sync + 'static {
+        self.state.register_native(name, params, return_type, func)
+    }
+
+    #[inline]
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    #[inline]
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use ast::{Type, Value};
+use console::Buffer;
+use error::{ErrorType, PalError};
+use interpreter::Interpreter;
+
+#[test]
+fn eval_keeps_state() {
+    let mut interpreter = Interpreter::new();
+
+    assert_eq!(None, interpreter.eval_str("let x = 2;\nint double(n) { return n * 2; }").unwrap());
+    assert_eq!(Some(val_int!(4)), interpreter.eval_str("double(x)").unwrap());
+    assert_eq!(Some(val_string!("two")), interpreter.eval_str("\"two\"").unwrap());
+    assert_eq!(Some(&val_int!(2)), interpreter.get_var("x"));
+    assert_eq!(None, interpreter.get_var("y"));
+}
+
+#[test]
+fn eval_same_let_twice() {
+    let mut interpreter = Interpreter::new();
+
+    interpreter.eval_str("let x = 1;").unwrap();
+    interpreter.eval_str("let x = 2;").unwrap();
+    assert_eq!(Some(&val_int!(2)), interpreter.get_var("x"));
+}
+
+#[test]
+fn set_var() {
+    let mut interpreter = Interpreter::new();
+
+    interpreter.set_var("x", val_int!(1));
+    interpreter.eval_str("x = x + 1;").unwrap();
+    assert_eq!(Some(&val_int!(2)), interpreter.get_var("x"));
+
+    interpreter.set_var("x", val_array!(val_int!(3)));
+    assert_eq!(Some(val_int!(3)), interpreter.eval_str("x[0]").unwrap());
+}
+
+#[test]
+fn call_function() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("int add(a, b) { return a + b; }\nvoid nothing() { }\nlet f = int (n) { return n; };")
+        .unwrap();
+
+    assert_eq!(Some(val_int!(3)), interpreter.call_function("add", vec![val_int!(1), val_int!(2)]).unwrap());
+    assert_eq!(None, interpreter.call_function("nothing", Vec::new()).unwrap());
+    assert_eq!(Some(val_int!(5)), interpreter.call_function("f", vec![val_int!(5)]).unwrap());
+
+    interpreter.register_native("negate", &[Type::Int], Type::Int, |args| match args[0] {
+        Value::Int(i) => Ok(Some(Value::Int(-i))),
+        _ => unreachable!(),
+    }).unwrap();
+    assert_eq!(Some(val_int!(-4)), interpreter.call_function("negate", vec![val_int!(4)]).unwrap());
+}
+
+#[test]
+fn errors() {
+    let mut interpreter = Interpreter::new();
+
+    match interpreter.call_function("missing", Vec::new()) {
+        Err(PalError::Runtime(e)) => assert_eq!(ErrorType::UndefinedFunction, e.err_type()),
+        other => panic!("expected an UndefinedFunction error, got {:?}", other),
+    }
+
+    match interpreter.eval_str("let x = 1 / 0;") {
+        Err(PalError::Runtime(e)) => assert_eq!(ErrorType::DivisionByZero, e.err_type()),
+        other => panic!("expected a DivisionByZero error, got {:?}", other),
+    }
+
+    assert_eq!("SyntaxError", interpreter.eval_str("let = 1;").unwrap_err().kind_name());
+}
+
+#[test]
+fn captured_output() {
+    let mut interpreter = Interpreter::new();
+    let console = Arc::new(Buffer::with_input(&["world"]));
+    interpreter.set_console(console.clone());
+
+    interpreter.eval_str("void greet(name) { print_line \"hello ${name}\"; }\ngreet(read_line());").unwrap();
+    interpreter.call_function("greet", vec![val_string!("again")]).unwrap();
+
+    assert_eq!("hello world\nhello again\n", console.output());
+}
@@ -1,83 +1,183 @@
+extern crate futures;
+extern crate libc;
 extern crate rl_sys;
+#[macro_use] extern crate serde_json;
 extern crate lalrpop_util;
+extern crate num;
 #[macro_use] extern crate stepper;
+extern crate tungstenite;
 extern crate unicode_xid;
 
+#[cfg(test)]
+extern crate url;
+
 #[macro_use]
 mod macros;
 
 mod ast;
+mod config;
+mod console;
+mod diagnostic;
 mod error;
 mod eval;
 mod grammar;
+mod interpreter;
+mod lsp;
 mod parser;
+mod server;
 mod stream;
 mod token;
 mod state;
 
 use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Write};
+use std::io::{self, Read};
 use std::path::Path;
 use std::sync::Arc;
 use std::thread;
 
-use error::Result;
+use ast::Statement;
+use error::PalResult;
 use parser::{parse_program, parse_stmt};
-use state::State;
 
-pub use stream::{Event, Stream};
+pub use ast::{Span, Type, Value};
+pub use config::{Config, Limits};
+pub use console::{Buffer, Console, Stdio};
+pub use diagnostic::{render_error, Style};
+pub use error::{ErrorType, PalError};
+pub use interpreter::Interpreter;
+pub use lsp::serve_lsp;
+pub use server::{event_to_json, listen_from_args, serve_tcp, serve_websocket, Request};
+pub use state::State;
+pub use stream::{AsyncStream, Event, ProgramHandle, Stream, Timeout};
+pub use token::{Token, Tokenizer};
 
 use rl_sys::readline;
 use rl_sys::history::{histfile, listmgmt};
 
-pub fn run_file(file_name: &str) -> Result<()> {
-    let mut file = File::open(file_name).expect("Unable to open file");
+pub fn run_file(file_name: &str) -> PalResult<()> {
+    run_file_with_config(file_name, Config::default())
+}
+
+// Runs a file, reporting any error that stops it, including one reading the file, to stderr.
+pub fn run_file_with_config(file_name: &str, config: Config) -> PalResult<()> {
     let mut program_str = String::new();
+    try!(File::open(file_name)
+        .and_then(|mut file| file.read_to_string(&mut program_str))
+        .map_err(|e| {
+            let e = PalError::Io(String::from(file_name), e);
+            Stdio.report_error(&e, file_name, "");
+            e
+        }));
+
+    run_named_program(file_name, &program_str, &mut State::with_config(config), &Stdio).map_err(|e| {
+        Stdio.report_error(&e, file_name, &program_str);
+        e
+    })
+}
 
-    file.read_to_string(&mut program_str).expect("Unable to read file");
-    run_program(&program_str)
+pub fn run_program(program_str: &str) -> PalResult<()> {
+    run_program_with_config(program_str, Config::default())
 }
 
-pub fn run_program(program_str: &str) -> Result<()> {
-    let program = parse_program(&program_str).unwrap();
-    let mut state = State::new();
+pub fn run_program_with_config(program_str: &str, config: Config) -> PalResult<()> {
+    run_named_program("<program>", program_str, &mut State::with_config(config), &Stdio)
+}
+
+// Runs a program with its output going to `console` and its input coming from it, e.g. to capture
+// the output in a `Buffer`. Errors are returned rather than reported to the console.
+pub fn run_program_with_console(program_str: &str, console: &Console) -> PalResult<()> {
+    run_program_with_console_and_config(program_str, console, Config::default())
+}
+
+pub fn run_program_with_console_and_config(program_str: &str, console: &Console,
+                                           config: Config) -> PalResult<()> {
+    run_named_program("<program>", program_str, &mut State::with_config(config), console)
+}
+
+// Runs a program in an existing state, e.g. one with native functions registered in it. Variables
+// and functions the program defines are kept in the state afterwards. The program is limited by
+// the state's config, so a state made with `State::with_config` can limit it.
+pub fn run_program_with_state(program_str: &str, state: &mut State) -> PalResult<()> {
+    run_named_program("<program>", program_str, state, &Stdio)
+}
+
+// Runs a program, using `name` in place of a file name when reporting the location of errors.
+fn run_named_program(name: &str, program_str: &str, state: &mut State,
+                     console: &Console) -> PalResult<()> {
+    let program = try!(parse(program_str));
+    state.start_run();
 
     for stmt in program {
-        try!(stmt.eval(&mut state, None));
+        try!(stmt.eval(state, console).map_err(|e| e.locate(name, program_str)));
     }
 
     Ok(())
 }
 
-pub fn run_program_with_stream(program_str: &str) -> Arc<Stream> {
-    let program = parse_program(&program_str).unwrap();
+// Parses a program, failing with every syntax error in it if there are any.
+fn parse(program_str: &str) -> PalResult<Vec<Statement>> {
+    let (program, errors) = parse_program(program_str);
+
+    match PalError::from_parse_errors(errors, program_str) {
+        Some(e) => Err(e),
+        None => Ok(program),
+    }
+}
+
+// Runs a program on another thread, which does its I/O through the returned stream. Syntax errors are
+// reported through the stream like runtime errors. The handle can be used to cancel the program or
+// wait for it to finish.
+pub fn run_program_with_stream(program_str: &str) -> (Arc<Stream>, ProgramHandle) {
+    run_program_with_stream_and_config(program_str, Config::default())
+}
+
+pub fn run_program_with_stream_and_config(program_str: &str, config: Config) -> (Arc<Stream>, ProgramHandle) {
     let stream = Arc::new(Stream::new());
     let cloned_stream = stream.clone();
-    let mut state = State::new();
+    let text = String::from(program_str);
 
-    thread::spawn(move || {
-        for stmt in program {
-            if let Err(e) = stmt.eval(&mut state, Some(cloned_stream.clone())) {
-                cloned_stream.write_output(&format!("{}", e));
-                break;
-            }
+    let thread = thread::spawn(move || {
+        let mut state = State::with_config(config);
+
+        let result = run_named_program("<program>", &text, &mut state, &*cloned_stream);
+
+        // A program cancelled during its last statement can still finish without an error, so the
+        // stream is checked whatever the result.
+        if cloned_stream.is_cancelled() {
+            cloned_stream.cancelled();
+        } else if let Err(e) = result {
+            cloned_stream.report_error(&e, "<program>", &text);
         }
 
         cloned_stream.finished();
     });
 
-    stream
+    (stream.clone(), ProgramHandle::new(stream, thread))
 }
 
-pub fn repl() {
+// Runs a program on another thread like `run_program_with_stream`, but with an asynchronous
+// interface to its events, so a host doesn't need a thread of its own waiting on each program's
+// events. The program itself still needs its own thread, which is parked while it waits for input;
+// see `AsyncStream`.
+pub fn run_program_async(program_str: &str) -> (AsyncStream, ProgramHandle) {
+    run_program_async_with_config(program_str, Config::default())
+}
+
+pub fn run_program_async_with_config(program_str: &str, config: Config) -> (AsyncStream, ProgramHandle) {
+    let (stream, handle) = run_program_with_stream_and_config(program_str, config);
+    (AsyncStream::new(stream), handle)
+}
+
+pub fn repl() -> PalResult<()> {
     let mut state = State::new();
-    let mut stderr = io::stderr();
 
     // Create history file if it doesn't already exist.
-    OpenOptions::new().write(true).create(true).truncate(false).open(".history").expect("Unable to create history file");
+    try!(OpenOptions::new().write(true).create(true).truncate(false).open(".history")
+        .map_err(|e| PalError::Io(String::from(".history"), e)));
 
     // Read in history from the file.
-    histfile::read(Some(Path::new(".history"))).expect("Unable to read history file");
+    try!(histfile::read(Some(Path::new(".history")))
+        .map_err(|e| PalError::Io(String::from(".history"), io::Error::new(io::ErrorKind::Other, e.to_string()))));
 
     while let Some(input) = readline::readline(">> ").unwrap() {
         if input.is_empty() {
@@ -88,15 +188,22 @@ pub fn repl() {
         listmgmt::add(&input).unwrap();
         let _ = histfile::write(Some(Path::new(".history")));
 
-        match parse_stmt(&input) {
-            Ok(stmt) => match stmt.eval(&mut state, None) {
-                Ok(_) => (),
-                Err(e) => writeln!(stderr, "{}", e).unwrap(),
-            },
-            Err(_) => println!("Sorry! That's an invalid statement"),
+        let (stmt, errors) = parse_stmt(&input);
+
+        // A statement is always returned if there weren't any syntax errors.
+        let result = match PalError::from_parse_errors(errors, &input) {
+            Some(e) => Err(e),
+            None => {
+                state.start_run();
+                stmt.unwrap().eval(&mut state, &Stdio).map_err(PalError::from)
+            }
         };
 
+        if let Err(e) = result {
+            Stdio.report_error(&e, "<repl>", &input);
+        }
     }
 
     println!("");
+    Ok(())
 }
@@ -0,0 +1,422 @@
+use std::cmp;
+
+use ast::{BinOp, Expr, ExprKind, Span, Statement, StmtKind, Type, Value};
+use error::PalError;
+use eval::BUILTINS;
+use parser::parse_program;
+use token::{Token, Tokenizer, KEYWORDS};
+
+// Something a name in a program refers to.
+#[derive(Clone, Debug)]
+pub enum DefinitionKind {
+    // A function defined with `Statement::Defun`, with its return type and parameters.
+    Function(Type, Vec<String>),
+    // A variable defined with `let`, with the expression giving its value.
+    Variable(Expr),
+    // The variable of a `for` loop, with the expression being iterated over.
+    LoopVariable(Expr),
+    // A parameter of a function or lambda.
+    Parameter,
+}
+
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    // Where the name is written in the definition.
+    pub span: Span,
+    // The part of the program that can refer to the definition, and where in it the definition
+    // starts being visible; a variable can't be used in its own `let`, but a function can be called
+    // before it's defined.
+    pub scope: Span,
+    pub visible_from: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    Keyword,
+    Function,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    // The signature of a function defined in the program.
+    pub detail: Option<String>,
+}
+
+// What an editor knows about the text of an open file. Everything is worked out from the partial
+// AST that the parser recovers, so a file with syntax errors still gets hover and completion.
+pub struct Document {
+    text: String,
+    errors: Vec<PalError>,
+    definitions: Vec<Definition>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let (program, errors) = parse_program(&text);
+        let errors = errors.into_iter().map(|e| PalError::from_parse_error(e, &text)).collect();
+
+        let mut definitions = Vec::new();
+        collect_block(&text, &program, Span::new(0, text.len()), &mut definitions);
+
+        Document { text: text, errors: errors, definitions: definitions }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // The syntax errors in the text.
+    pub fn errors(&self) -> &[PalError] {
+        &self.errors
+    }
+
+    // Describes the function or variable named at the offset, giving the span of the name along
+    // with e.g. `int add(a, b)` or `total: float`.
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        let (name, span) = match self.ident_at(offset) {
+            Some(ident) => ident,
+            None => return None,
+        };
+
+        self.definition_of(name, span).map(|def| {
+            let description = match def.kind {
+                DefinitionKind::Function(ref return_type, ref params) =>
+                    format!("{} {}({})", return_type, def.name, params.join(", ")),
+                _ => format!("{}: {}", def.name, self.type_of_definition(def)),
+            };
+
+            (span, description)
+        })
+    }
+
+    // Finds where the function or variable named at the offset is defined.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        self.ident_at(offset).and_then(|(name, span)| self.definition_of(name, span)).map(|def| def.span)
+    }
+
+    // The keywords of the language, followed by its builtin functions and the functions defined in
+    // the program.
+    pub fn completions(&self) -> Vec<Completion> {
+        let keywords = KEYWORDS.iter()
+            .map(|k| Completion { label: String::from(*k), kind: CompletionKind::Keyword, detail: None });
+        let builtins = BUILTINS.iter()
+            .filter(|b| !KEYWORDS.contains(*b) && !self.definitions.iter().any(|def| def.name == **b))
+            .map(|b| Completion { label: String::from(*b), kind: CompletionKind::Function, detail: None });
+
+        let mut completions: Vec<_> = keywords.chain(builtins).collect();
+
+        for def in &self.definitions {
+            if let DefinitionKind::Function(ref return_type, ref params) = def.kind {
+                if !completions.iter().any(|c| c.label == def.name) {
+                    completions.push(Completion {
+                        label: def.name.clone(),
+                        kind: CompletionKind::Function,
+                        detail: Some(format!("{} {}({})", return_type, def.name, params.join(", "))),
+                    });
+                }
+            }
+        }
+
+        completions
+    }
+
+    // Works out the type of the value an expression evaluates to as far as possible without running
+    // the program, giving `Type::Any` when it can't be known.
+    pub fn type_of(&self, exp: &Expr) -> Type {
+        match exp.kind {
+            ExprKind::Array(_) | ExprKind::Keys(_) | ExprKind::Letters(_) | ExprKind::Range(..) |
+            ExprKind::Step(..) | ExprKind::Values(_) => Type::Array,
+            ExprKind::ArrayElement(..) => Type::Any,
+            ExprKind::BinExp(ref exp1, ref op, ref exp2) => match *op {
+                BinOp::And | BinOp::Or | BinOp::Equal | BinOp::NotEqual | BinOp::GreaterOrEqual |
+                BinOp::GreaterThan | BinOp::LessOrEqual | BinOp::LessThan => Type::Bool,
+                BinOp::Concat => Type::Array,
+                BinOp::Plus | BinOp::Minus | BinOp::Times | BinOp::Divide | BinOp::Modulus =>
+                    match (self.type_of(exp1), self.type_of(exp2)) {
+                        (Type::Int, Type::Int) => Type::Int,
+                        (Type::Float, Type::Int) | (Type::Int, Type::Float) | (Type::Float, Type::Float) => Type::Float,
+                        _ => Type::Any,
+                    },
+            },
+            ExprKind::Call(ref func, ref args) => self.return_type_of(func, args),
+            ExprKind::Interpolation(_) | ExprKind::ReadLine => Type::Str,
+            ExprKind::Lambda(..) => Type::Function,
+            ExprKind::Length(_) | ExprKind::ToInt(_) => Type::Int,
+            ExprKind::Map(_) => Type::Map,
+            ExprKind::Not(_) => Type::Bool,
+            ExprKind::ToFloat(_) => Type::Float,
+            ExprKind::Value(ref val) => match *val {
+                Value::Array(_) => Type::Array,
+                Value::BigInt(_) | Value::Int(_) => Type::Int,
+                Value::Bool(_) => Type::Bool,
+                Value::Float(_) => Type::Float,
+                Value::Function(_) => Type::Function,
+                Value::Map(_) => Type::Map,
+                Value::Str(_) => Type::Str,
+            },
+            ExprKind::Var(ref name) => match self.resolve(name, exp.span.lo) {
+                Some(def) => self.type_of_definition(def),
+                None => Type::Any,
+            },
+        }
+    }
+
+    fn type_of_definition(&self, def: &Definition) -> Type {
+        match def.kind {
+            DefinitionKind::Function(..) => Type::Function,
+            DefinitionKind::Variable(ref exp) => self.type_of(exp),
+            // Arrays are iterated over by their elements, and maps by their keys.
+            DefinitionKind::LoopVariable(ref exp) => match exp.kind {
+                ExprKind::Range(..) => Type::Int,
+                ExprKind::Keys(_) | ExprKind::Letters(_) => Type::Str,
+                _ if self.type_of(exp) == Type::Map => Type::Str,
+                _ => Type::Any,
+            },
+            DefinitionKind::Parameter => Type::Any,
+        }
+    }
+
+    fn return_type_of(&self, func: &Expr, args: &[Expr]) -> Type {
+        let name = match func.kind {
+            ExprKind::Lambda(ref return_type, _, _) => return return_type.clone(),
+            ExprKind::Var(ref name) => name,
+            _ => return Type::Any,
+        };
+
+        match self.resolve(name, func.span.lo).map(|def| &def.kind) {
+            Some(&DefinitionKind::Function(ref return_type, _)) => return_type.clone(),
+            Some(&DefinitionKind::Variable(ref exp)) => match exp.kind {
+                ExprKind::Lambda(ref return_type, _, _) => return_type.clone(),
+                _ => Type::Any,
+            },
+            Some(_) => Type::Any,
+            None => match &name[..] {
+                "contains" => Type::Bool,
+                "filter" | "map" | "slice" | "sort" => Type::Array,
+                "index_of" => Type::Int,
+                "join" => Type::Str,
+                "reverse" => args.first().map_or(Type::Any, |arg| self.type_of(arg)),
+                _ => Type::Any,
+            },
+        }
+    }
+
+    // Finds the definition that a name used at the given offset refers to: the one in the innermost
+    // scope, or the latest one if there are several in the same scope.
+    fn resolve(&self, name: &str, offset: usize) -> Option<&Definition> {
+        self.definitions.iter()
+            .filter(|def| def.name == name && def.scope.lo <= offset && offset <= def.scope.hi)
+            .filter(|def| def.visible_from <= offset)
+            .max_by_key(|def| (def.scope.lo, def.visible_from))
+    }
+
+    // Finds the definition for a name at the given span, which might be the name in the definition
+    // itself.
+    fn definition_of(&self, name: &str, span: Span) -> Option<&Definition> {
+        match self.definitions.iter().find(|def| def.span == span) {
+            Some(def) => Some(def),
+            None => self.resolve(name, span.lo),
+        }
+    }
+
+    // Finds the identifier that the offset is in or just after.
+    fn ident_at(&self, offset: usize) -> Option<(&str, Span)> {
+        for token in Tokenizer::new(&self.text, 0) {
+            match token {
+                Ok((lo, _, _)) if lo > offset => break,
+                Ok((lo, Token::Ident(name), hi)) if offset <= hi => return Some((name, Span::new(lo, hi))),
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        }
+
+        None
+    }
+}
+
+// Converts a byte offset in the text to an LSP position: a zero-indexed line, and a character
+// within that line counted in UTF-16 code units.
+pub fn offset_to_position(text: &str, offset: usize) -> (usize, usize) {
+    let offset = cmp::min(offset, text.len());
+    let mut line = 0;
+    let mut character = 0;
+
+    for (i, c) in text.char_indices() {
+        if i >= offset {
+            break;
+        }
+
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += c.len_utf16();
+        }
+    }
+
+    (line, character)
+}
+
+// Converts an LSP position to a byte offset in the text. Positions past the end of a line are
+// taken to be at the end of it.
+pub fn position_to_offset(text: &str, line: usize, character: usize) -> usize {
+    let mut current_line = 0;
+    let mut current_character = 0;
+
+    for (i, c) in text.char_indices() {
+        if current_line == line && (current_character >= character || c == '\n') {
+            return i;
+        }
+
+        if c == '\n' {
+            current_line += 1;
+        } else if current_line == line {
+            current_character += c.len_utf16();
+        }
+    }
+
+    text.len()
+}
+
+// Collects the definitions in a block of statements, which are visible within `scope`.
+fn collect_block(text: &str, stmts: &[Statement], scope: Span, defs: &mut Vec<Definition>) {
+    for stmt in stmts {
+        collect_stmt(text, stmt, scope, defs);
+    }
+}
+
+// The names defined by a statement are found by tokenizing it, since the AST doesn't keep their
+// spans. They're the first identifiers in the statement, as only keywords, types and labels can come
+// before them.
+fn collect_stmt(text: &str, stmt: &Statement, scope: Span, defs: &mut Vec<Definition>) {
+    match stmt.kind {
+        StmtKind::Defun(ref return_type, ref name, ref params, ref body) => {
+            let names = idents(text, stmt.span);
+
+            if let Some(&span) = names.first() {
+                let kind = DefinitionKind::Function(return_type.clone(), params.clone());
+                defs.push(definition(name, kind, span, scope, scope.lo));
+            }
+
+            collect_params(params, &names[cmp::min(1, names.len())..], stmt.span, defs);
+            collect_block(text, body, stmt.span, defs);
+        }
+        StmtKind::Let(ref name, ref exp) => {
+            collect_exp(text, exp, defs);
+
+            if let Some(&span) = idents(text, stmt.span).first() {
+                defs.push(definition(name, DefinitionKind::Variable(exp.clone()), span, scope, stmt.span.hi));
+            }
+        }
+        StmtKind::For(ref label, ref var, ref exp, ref body) => {
+            collect_exp(text, exp, defs);
+
+            // Skip the label of the loop.
+            let skip = if label.is_some() { 1 } else { 0 };
+
+            if let Some(&span) = idents(text, stmt.span).get(skip) {
+                let kind = DefinitionKind::LoopVariable(exp.clone());
+                defs.push(definition(var, kind, span, stmt.span, exp.span.hi));
+            }
+
+            collect_block(text, body, stmt.span, defs);
+        }
+        StmtKind::If(ref exp, ref block1, ref block2) => {
+            collect_exp(text, exp, defs);
+            collect_block(text, block1, stmt.span, defs);
+            collect_block(text, block2, stmt.span, defs);
+        }
+        StmtKind::While(_, ref exp, ref body) => {
+            collect_exp(text, exp, defs);
+            collect_block(text, body, stmt.span, defs);
+        }
+        StmtKind::ArrayElemAssign(_, ref index, ref indexes, ref exp) => {
+            collect_exp(text, index, defs);
+            collect_exps(text, indexes, defs);
+            collect_exp(text, exp, defs);
+        }
+        StmtKind::Delete(_, ref index, ref indexes) => {
+            collect_exp(text, index, defs);
+            collect_exps(text, indexes, defs);
+        }
+        StmtKind::Print(ref exp) | StmtKind::PrintLine(ref exp) | StmtKind::Return(ref exp) |
+        StmtKind::VarAssign(_, ref exp) => collect_exp(text, exp, defs),
+        StmtKind::VoidCall(ref func, ref args) => {
+            collect_exp(text, func, defs);
+            collect_exps(text, args, defs);
+        }
+        StmtKind::Break(_) | StmtKind::Continue(_) | StmtKind::Error => (),
+    }
+}
+
+// Collects the parameters and bodies of the lambdas in an expression.
+fn collect_exp(text: &str, exp: &Expr, defs: &mut Vec<Definition>) {
+    match exp.kind {
+        ExprKind::Array(ref exps) | ExprKind::Interpolation(ref exps) => collect_exps(text, exps, defs),
+        ExprKind::ArrayElement(_, ref index, ref indexes) => {
+            collect_exp(text, index, defs);
+            collect_exps(text, indexes, defs);
+        }
+        ExprKind::BinExp(ref exp1, _, ref exp2) | ExprKind::Range(ref exp1, ref exp2) => {
+            collect_exp(text, exp1, defs);
+            collect_exp(text, exp2, defs);
+        }
+        ExprKind::Call(ref func, ref args) => {
+            collect_exp(text, func, defs);
+            collect_exps(text, args, defs);
+        }
+        ExprKind::Keys(ref exp) | ExprKind::Length(ref exp) | ExprKind::Letters(ref exp) |
+        ExprKind::Not(ref exp) | ExprKind::ToFloat(ref exp) | ExprKind::ToInt(ref exp) |
+        ExprKind::Values(ref exp) => collect_exp(text, exp, defs),
+        ExprKind::Lambda(_, ref params, ref body) => {
+            collect_params(params, &idents(text, exp.span), exp.span, defs);
+            collect_block(text, body, exp.span, defs);
+        }
+        ExprKind::Map(ref entries) => for &(ref key, ref val) in entries {
+            collect_exp(text, key, defs);
+            collect_exp(text, val, defs);
+        },
+        ExprKind::Step(ref start, ref end, ref step) => {
+            collect_exp(text, start, defs);
+            collect_exp(text, end, defs);
+            collect_exp(text, step, defs);
+        }
+        ExprKind::ReadLine | ExprKind::Value(_) | ExprKind::Var(_) => (),
+    }
+}
+
+fn collect_exps(text: &str, exps: &[Expr], defs: &mut Vec<Definition>) {
+    for exp in exps {
+        collect_exp(text, exp, defs);
+    }
+}
+
+// Adds the parameters of a function, given the spans of the identifiers starting with the first
+// parameter.
+fn collect_params(params: &[String], spans: &[Span], scope: Span, defs: &mut Vec<Definition>) {
+    for (param, &span) in params.iter().zip(spans) {
+        defs.push(definition(param, DefinitionKind::Parameter, span, scope, scope.lo));
+    }
+}
+
+fn definition(name: &str, kind: DefinitionKind, span: Span, scope: Span, visible_from: usize) -> Definition {
+    Definition { name: String::from(name), kind: kind, span: span, scope: scope, visible_from: visible_from }
+}
+
+// Returns the spans of the identifiers in part of the text, in order.
+fn idents(text: &str, span: Span) -> Vec<Span> {
+    let hi = cmp::min(span.hi, text.len());
+    let lo = cmp::min(span.lo, hi);
+
+    Tokenizer::new(&text[lo..hi], lo)
+        .take_while(|token| token.is_ok())
+        .filter_map(|token| match token {
+            Ok((lo, Token::Ident(_), hi)) => Some(Span::new(lo, hi)),
+            _ => None,
+        })
+        .collect()
+}
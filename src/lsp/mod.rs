@@ -0,0 +1,233 @@
+#[cfg(test)]
+mod test;
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{self, Value as Json};
+
+use ast::Span;
+use self::analysis::{offset_to_position, position_to_offset, CompletionKind, Document};
+
+// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP constants for the messages the server sends.
+const FULL_SYNC: i64 = 1;
+const SEVERITY_ERROR: i64 = 1;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_KEYWORD: i64 = 14;
+
+// Serves the Language Server Protocol for pal files until the client sends `exit` or closes the
+// input. Messages are JSON-RPC with a `Content-Length` header, as an editor sends them over the
+// standard input and output of a language server.
+//
+// The server publishes syntax errors as diagnostics whenever a file is opened or changed, and
+// answers hover, go-to-definition and completion requests.
+pub fn serve_lsp<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut server = Server { documents: HashMap::new() };
+
+    while let Some(body) = try!(read_message(&mut input)) {
+        let message: Json = match serde_json::from_str(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                try!(write_message(&mut output, &error_response(&Json::Null, PARSE_ERROR, &format!("{}", e))));
+                continue;
+            }
+        };
+
+        if message["method"] == "exit" {
+            break;
+        }
+
+        for reply in server.handle(&message) {
+            try!(write_message(&mut output, &reply));
+        }
+    }
+
+    Ok(())
+}
+
+struct Server {
+    // The text of each open file, by URI.
+    documents: HashMap<String, Document>,
+}
+
+impl Server {
+    // Handles a request or notification from the client, returning the messages to send back.
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let params = &message["params"];
+        let id = &message["id"];
+
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            None => return vec![error_response(id, INVALID_REQUEST, "The message needs a `method` string")],
+        };
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": FULL_SYNC,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "pal-lsp" },
+            }),
+            "shutdown" => Json::Null,
+            "textDocument/didOpen" => {
+                let uri = uri(params);
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                return vec![self.update(uri, String::from(text))];
+            }
+            "textDocument/didChange" => {
+                // Only full syncing is supported, so the last change has the whole text.
+                let uri = uri(params);
+                let text = params["contentChanges"].as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or("");
+                return vec![self.update(uri, String::from(text))];
+            }
+            "textDocument/didClose" => {
+                let uri = uri(params);
+                self.documents.remove(&uri);
+                return vec![notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }))];
+            }
+            "textDocument/hover" => match self.lookup(params) {
+                Some((doc, offset)) => match doc.hover(offset) {
+                    Some((span, description)) => json!({
+                        "contents": { "kind": "plaintext", "value": description },
+                        "range": range(doc.text(), span),
+                    }),
+                    None => Json::Null,
+                },
+                None => Json::Null,
+            },
+            "textDocument/definition" => match self.lookup(params) {
+                Some((doc, offset)) => match doc.definition(offset) {
+                    Some(span) => json!({ "uri": uri(params), "range": range(doc.text(), span) }),
+                    None => Json::Null,
+                },
+                None => Json::Null,
+            },
+            "textDocument/completion" => match self.documents.get(&uri(params)) {
+                Some(doc) => Json::Array(doc.completions().into_iter().map(|c| json!({
+                    "label": c.label,
+                    "kind": match c.kind {
+                        CompletionKind::Keyword => COMPLETION_KEYWORD,
+                        CompletionKind::Function => COMPLETION_FUNCTION,
+                    },
+                    "detail": c.detail,
+                })).collect()),
+                None => Json::Array(Vec::new()),
+            },
+            // Notifications that aren't handled, like `initialized`, are ignored.
+            _ if id.is_null() => return Vec::new(),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("`{}` isn't supported", method))],
+        };
+
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    // Replaces the text of a file, returning the notification of its diagnostics.
+    fn update(&mut self, uri: String, text: String) -> Json {
+        let doc = Document::new(text);
+
+        let diagnostics: Vec<_> = doc.errors().iter().map(|e| {
+            let span = e.span().unwrap_or_default();
+            let mut message = e.message();
+
+            for note in e.notes() {
+                message.push_str(&format!("\n{}", note));
+            }
+
+            json!({
+                "range": range(doc.text(), span),
+                "severity": SEVERITY_ERROR,
+                "source": "pal",
+                "message": message,
+            })
+        }).collect();
+
+        self.documents.insert(uri.clone(), doc);
+        notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }))
+    }
+
+    // Finds the file and the offset in it of a request's `textDocument` and `position`.
+    fn lookup(&self, params: &Json) -> Option<(&Document, usize)> {
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+
+        self.documents.get(&uri(params)).map(|doc| (doc, position_to_offset(doc.text(), line, character)))
+    }
+}
+
+fn uri(params: &Json) -> String {
+    String::from(params["textDocument"]["uri"].as_str().unwrap_or(""))
+}
+
+fn range(text: &str, span: Span) -> Json {
+    let (start_line, start_character) = offset_to_position(text, span.lo);
+    let (end_line, end_character) = offset_to_position(text, span.hi);
+
+    json!({
+        "start": { "line": start_line, "character": start_character },
+        "end": { "line": end_line, "character": end_character },
+    })
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn error_response(id: &Json, code: i64, message: &str) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Reads the body of the next message, or returns `None` at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+
+        if try!(input.read_line(&mut line)) == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_right();
+
+        if line.is_empty() {
+            // Skip any blank lines between messages.
+            if length.is_some() {
+                break;
+            }
+
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ':');
+
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.trim().to_lowercase() == "content-length" {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    try!(input.read_exact(&mut body));
+
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    try!(write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body));
+    output.flush()
+}
@@ -0,0 +1,194 @@
+use std::io::Cursor;
+
+use serde_json::{self, Value as Json};
+
+use ast::Span;
+use lsp::{read_message, serve_lsp};
+use lsp::analysis::{offset_to_position, position_to_offset, CompletionKind, Document};
+use token::{Token, Tokenizer, KEYWORDS};
+
+const PROGRAM: &'static str = "\
+int add(a, b) {
+    return a + b;
+}
+
+let total = add(1, 2);
+let ratio = to_float(total) / 2;
+
+for i in range(1, 3) {
+    let name = \"n${i}\";
+    print_line name;
+}
+";
+
+// Returns the offset of the first occurrence of `needle` in the text.
+fn at(text: &str, needle: &str) -> usize {
+    text.find(needle).unwrap()
+}
+
+fn hover(doc: &Document, offset: usize) -> Option<String> {
+    doc.hover(offset).map(|(_, description)| description)
+}
+
+// Frames a message the way an LSP client does.
+fn frame(json: Json) -> String {
+    let body = json.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[test]
+fn positions() {
+    // `é` is two bytes but one UTF-16 unit, and `😀` is four bytes but two UTF-16 units.
+    let text = "é\n😀x";
+
+    assert_eq!((0, 1), offset_to_position(text, 2));
+    assert_eq!((1, 2), offset_to_position(text, 7));
+    assert_eq!(7, position_to_offset(text, 1, 2));
+    assert_eq!(2, position_to_offset(text, 0, 10));
+    assert_eq!(text.len(), position_to_offset(text, 5, 0));
+}
+
+#[test]
+fn keywords() {
+    for keyword in KEYWORDS {
+        let tokens: Vec<_> = Tokenizer::new(keyword, 0).collect();
+
+        assert_eq!(1, tokens.len());
+        assert!(match tokens[0] { Ok((_, Token::Ident(_), _)) => false, Ok(_) => true, Err(_) => false },
+                "`{}` isn't a keyword", keyword);
+    }
+}
+
+#[test]
+fn syntax_errors() {
+    let doc = Document::new(String::from("let x = ;\nprint 1;\nlet y = 1 2;\n"));
+
+    let spans: Vec<_> = doc.errors().iter().map(|e| e.span()).collect();
+    assert_eq!(vec![Some(Span::new(8, 9)), Some(Span::new(29, 30))], spans);
+    assert!(Document::new(String::from(PROGRAM)).errors().is_empty());
+}
+
+#[test]
+fn hover_types() {
+    let doc = Document::new(String::from(PROGRAM));
+
+    assert_eq!(Some(String::from("int add(a, b)")), hover(&doc, at(PROGRAM, "add(1")));
+    assert_eq!(Some(String::from("total: int")), hover(&doc, at(PROGRAM, "total")));
+    assert_eq!(Some(String::from("ratio: float")), hover(&doc, at(PROGRAM, "ratio") + 2));
+    assert_eq!(Some(String::from("i: int")), hover(&doc, at(PROGRAM, "${i}") + 2));
+    assert_eq!(Some(String::from("name: string")), hover(&doc, at(PROGRAM, "name;")));
+    assert_eq!(Some(String::from("a: any")), hover(&doc, at(PROGRAM, "a + b")));
+
+    // Keywords and literals aren't described.
+    assert_eq!(None, hover(&doc, at(PROGRAM, "print_line")));
+    assert_eq!(None, hover(&doc, at(PROGRAM, "2;")));
+}
+
+#[test]
+fn hover_in_unclosed_block() {
+    // A block that's still being typed doesn't lose what was defined before it.
+    let text = "int add(a, b) {\n    return a + b;\n}\nlet x = add(1, 2);\nif (x > 1) {\n    print x";
+    let doc = Document::new(String::from(text));
+
+    assert!(!doc.errors().is_empty());
+    assert_eq!(Some(String::from("x: int")), hover(&doc, text.rfind('x').unwrap()));
+    assert_eq!(Some(String::from("int add(a, b)")), hover(&doc, at(text, "add(1")));
+    assert!(doc.completions().iter().any(|c| c.label == "add"));
+}
+
+#[test]
+fn definitions() {
+    let doc = Document::new(String::from(PROGRAM));
+
+    assert_eq!(Some(Span::new(4, 7)), doc.definition(at(PROGRAM, "add(1")));
+    assert_eq!(Some(Span::new(4, 7)), doc.definition(4));
+
+    let total = at(PROGRAM, "total");
+    assert_eq!(Some(Span::new(total, total + 5)), doc.definition(at(PROGRAM, "total)")));
+
+    // Parameters shadow variables outside the function, and a variable can't be used before its
+    // `let`.
+    let text = "print y;\nlet x = 1;\nvoid f(x) { print x; }\nprint x;\nlet y = 2;";
+    let doc = Document::new(String::from(text));
+    let param = at(text, "x)");
+    let var = at(text, "x =");
+
+    assert_eq!(Some(Span::new(param, param + 1)), doc.definition(at(text, "x; }")));
+    assert_eq!(Some(Span::new(var, var + 1)), doc.definition(text.rfind("x;").unwrap()));
+    assert_eq!(None, doc.definition(at(text, "y;")));
+}
+
+#[test]
+fn completions() {
+    let completions = Document::new(String::from(PROGRAM)).completions();
+    let find = |label: &str| completions.iter().find(|c| c.label == label).cloned();
+
+    assert_eq!(CompletionKind::Keyword, find("while").unwrap().kind);
+    assert_eq!(CompletionKind::Function, find("join").unwrap().kind);
+
+    let add = find("add").unwrap();
+    assert_eq!(CompletionKind::Function, add.kind);
+    assert_eq!(Some(String::from("int add(a, b)")), add.detail);
+
+    // Variables are only offered by the editor's own word completion.
+    assert!(find("total").is_none());
+}
+
+#[test]
+fn session() {
+    let uri = "file:///test.pal";
+    let messages = vec![
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": uri, "languageId": "pal", "version": 1, "text": "let x = ;\nprint x;" },
+        }}),
+        json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "let x = 1;\nprint x;" }],
+        }}),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {
+            "textDocument": { "uri": uri }, "position": { "line": 1, "character": 6 },
+        }}),
+        json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/definition", "params": {
+            "textDocument": { "uri": uri }, "position": { "line": 1, "character": 6 },
+        }}),
+        json!({ "jsonrpc": "2.0", "id": 4, "method": "textDocument/formatting", "params": {} }),
+        json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+        // Nothing after `exit` is handled.
+        json!({ "jsonrpc": "2.0", "id": 6, "method": "shutdown" }),
+    ];
+
+    let input: String = messages.into_iter().map(frame).collect();
+    let mut output = Vec::new();
+    serve_lsp(Cursor::new(input), &mut output).unwrap();
+
+    let mut output = Cursor::new(output);
+    let mut replies = Vec::new();
+
+    while let Some(body) = read_message(&mut output).unwrap() {
+        replies.push(serde_json::from_str::<Json>(&body).unwrap());
+    }
+
+    assert_eq!(7, replies.len());
+    assert_eq!(true, replies[0]["result"]["capabilities"]["hoverProvider"]);
+
+    assert_eq!("textDocument/publishDiagnostics", replies[1]["method"]);
+    assert_eq!(json!({ "start": { "line": 0, "character": 8 }, "end": { "line": 0, "character": 9 } }),
+               replies[1]["params"]["diagnostics"][0]["range"]);
+    assert_eq!(json!([]), replies[2]["params"]["diagnostics"]);
+
+    assert_eq!(json!({
+        "contents": { "kind": "plaintext", "value": "x: int" },
+        "range": { "start": { "line": 1, "character": 6 }, "end": { "line": 1, "character": 7 } },
+    }), replies[3]["result"]);
+    assert_eq!(json!({
+        "uri": uri,
+        "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 5 } },
+    }), replies[4]["result"]);
+
+    assert_eq!(-32601, replies[5]["error"]["code"]);
+    assert_eq!(4, replies[5]["id"]);
+    assert_eq!(json!({ "jsonrpc": "2.0", "id": 5, "result": null }), replies[6]);
+}
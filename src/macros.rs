@@ -4,11 +4,23 @@ macro_rules! stringify_from {
     ($id:ident) => (String::from(stringify!($id)))
 }
 
+macro_rules! unspanned_exp {
+    ($kind:expr) => (Expr::new($kind, Span::default()))
+}
+
+macro_rules! unspanned_stmt {
+    ($kind:expr) => (Statement::new($kind, Span::default()))
+}
+
 // Values -----------------------------------------------------------------------------------------
 macro_rules! val_array {
     ($($val:expr),*) => (Value::Array(vec![$($val),*]))
 }
 
+macro_rules! val_float {
+    ($float:expr) => (Value::Float($float))
+}
+
 macro_rules! val_int {
     ($int:expr) => (Value::Int($int))
 }
@@ -19,112 +31,134 @@ macro_rules! val_string {
 
 // Expressions ------------------------------------------------------------------------------------
 macro_rules! array {
-    ($($exp:expr),*) => (Expr::Array(vec![$($exp),*]))
+    ($($exp:expr),*) => (unspanned_exp!(ExprKind::Array(vec![$($exp),*])))
 }
 
 macro_rules! bin_exp {
-    ($exp1:expr, $op:expr, $exp2:expr) => (Expr::BinExp(Box::new($exp1), $op, Box::new($exp2)))
+    ($exp1:expr, $op:expr, $exp2:expr) => (unspanned_exp!(ExprKind::BinExp(Box::new($exp1), $op, Box::new($exp2))))
 }
 
 macro_rules! boolean {
-    ($boolean:expr) => (Expr::Value(Value::Bool($boolean)))
+    ($boolean:expr) => (unspanned_exp!(ExprKind::Value(Value::Bool($boolean))))
 }
 
 macro_rules! call {
-    ($name:ident ($($arg:expr),*)) => (Expr::Call(stringify_from!($name), vec![$($arg),*]))
+    ($name:ident ($($arg:expr),*)) => (unspanned_exp!(ExprKind::Call(Box::new(var!($name)), vec![$($arg),*])))
+}
+
+macro_rules! float {
+    ($f:expr) => (unspanned_exp!(ExprKind::Value(Value::Float($f))))
 }
 
 macro_rules! index {
-    ($array:ident[$index1:expr]$([$index2:expr])*) => (Expr::ArrayElement(stringify_from!($array), Box::new($index1), vec![$($index2),*]))
+    ($array:ident[$index1:expr]$([$index2:expr])*) => (unspanned_exp!(ExprKind::ArrayElement(stringify_from!($array), Box::new($index1), vec![$($index2),*])))
 }
 
 macro_rules! int {
-    ($i:expr) => (Expr::Value(Value::Int($i)))
+    ($i:expr) => (unspanned_exp!(ExprKind::Value(Value::Int($i))))
 }
 
 macro_rules! length {
-    ($exp:expr) => (Expr::Length(Box::new($exp)))
+    ($exp:expr) => (unspanned_exp!(ExprKind::Length(Box::new($exp))))
 }
 
 macro_rules! letters {
-    ($exp:expr) => (Expr::Letters(Box::new($exp)))
+    ($exp:expr) => (unspanned_exp!(ExprKind::Letters(Box::new($exp))))
 }
 
 macro_rules! not {
-    ($exp:expr) => (Expr::Not(Box::new($exp)))
+    ($exp:expr) => (unspanned_exp!(ExprKind::Not(Box::new($exp))))
 }
 
 macro_rules! range {
-    ($start:expr, $end:expr) => (Expr::Range(Box::new($start), Box::new($end)))
+    ($start:expr, $end:expr) => (unspanned_exp!(ExprKind::Range(Box::new($start), Box::new($end))))
 }
 
 macro_rules! step_exp {
-    ($start:expr, $end:expr, $step:expr) => (Expr::Step(Box::new($start), Box::new($end), Box::new($step)))
+    ($start:expr, $end:expr, $step:expr) => (unspanned_exp!(ExprKind::Step(Box::new($start), Box::new($end), Box::new($step))))
 }
 
 macro_rules! string {
-    ($string:expr) => (Expr::Value(val_string!($string)))
+    ($string:expr) => (unspanned_exp!(ExprKind::Value(val_string!($string))))
 }
 
 macro_rules! var {
-    ($var:ident) => (Expr::Var(stringify_from!($var)))
+    ($var:ident) => (unspanned_exp!(ExprKind::Var(stringify_from!($var))))
 }
 
 // Statements -------------------------------------------------------------------------------------
 
+macro_rules! stmt_break {
+    () => (unspanned_stmt!(StmtKind::Break(None)));
+    ($label:ident) => (unspanned_stmt!(StmtKind::Break(Some(stringify_from!($label)))))
+}
+
+macro_rules! stmt_continue {
+    () => (unspanned_stmt!(StmtKind::Continue(None)));
+    ($label:ident) => (unspanned_stmt!(StmtKind::Continue(Some(stringify_from!($label)))))
+}
+
 macro_rules! stmt_defun {
     ($ty:expr, $name:ident ($($param:ident),*) { $($stmt:expr);* }) =>
-        (Statement::Defun($ty, stringify_from!($name), vec![$(stringify_from!($param)),*], vec![$($stmt),*]))
+        (unspanned_stmt!(StmtKind::Defun($ty, stringify_from!($name), vec![$(stringify_from!($param)),*], vec![$($stmt),*])))
 }
 
 macro_rules! stmt_delete {
-    ($var:ident[$index1:expr]$([$index2:expr])*) => (Statement::Delete(stringify_from!($var), $index1, vec![$($index2),*]));
+    ($var:ident[$index1:expr]$([$index2:expr])*) => (unspanned_stmt!(StmtKind::Delete(stringify_from!($var), $index1, vec![$($index2),*])));
 }
 
 macro_rules! stmt_for {
-    ($var:ident <- $arr:expr, { $($stmt:expr);* }) => (Statement::For(stringify_from!($var), $arr, vec![$($stmt),*]))
+    ($var:ident <- $arr:expr, { $($stmt:expr);* }) => (unspanned_stmt!(StmtKind::For(None, stringify_from!($var), $arr, vec![$($stmt),*])));
+    ($label:ident: $var:ident <- $arr:expr, { $($stmt:expr);* }) =>
+        (unspanned_stmt!(StmtKind::For(Some(stringify_from!($label)), stringify_from!($var), $arr, vec![$($stmt),*])))
 }
 
 macro_rules! stmt_if {
     // if (cond) { ... }
-    (($clause:expr) { $($stmt:expr);* }) => (Statement::If($clause, vec![$($stmt),*], Vec::new()));
+    (($clause:expr) { $($stmt:expr);* }) => (unspanned_stmt!(StmtKind::If($clause, vec![$($stmt),*], Vec::new())));
 
     // if (cond) { ... } else { ...}
     (($clause:expr) { $($stmt1:expr);* } els { $($stmt2:expr);* }) =>
-        (Statement::If($clause, vec![$($stmt1),*], vec![$($stmt2),*]));
+        (unspanned_stmt!(StmtKind::If($clause, vec![$($stmt1),*], vec![$($stmt2),*])));
 
     // if (cond1) { ... } else if (cond2) { ...} else if (cond3) { ... } ... else if (condN) { ...}
     (($clause1:expr) { $($stmt1:expr);* }
     elsif ($clause2:expr) { $($stmt2:expr);* } $(elsif ($clause3:expr) { $($stmt3:expr);* }),*) =>
-        (Statement::If(($clause1), vec![$($stmt1),*], vec![
+        (unspanned_stmt!(StmtKind::If(($clause1), vec![$($stmt1),*], vec![
             stmt_if!(($clause2) { $($stmt2);* } $(elsif ($clause3) { $($stmt3);* }),*)
-        ]));
+        ])));
 
     // if (cond1) { ... } else if (cond2) { ...} else if (cond3) { ... } ... else if (condN) { ...} else { ... }
     (($clause1:expr) { $($stmt1:expr);* }
      elsif ($clause2:expr) { $($stmt2:expr);* } $(elsif ($clause3:expr) { $($stmt3:expr);* }),*
      els { $($stmt4:expr);* }) =>
-        (Statement::If(($clause1), vec![$($stmt1),*], vec![
+        (unspanned_stmt!(StmtKind::If(($clause1), vec![$($stmt1),*], vec![
             stmt_if!(($clause2) { $($stmt2);* } $(elsif ($clause3) { $($stmt3);* }),*  els { $($stmt4);* })
-        ]))
+        ])))
 }
 
 macro_rules! stmt_let {
-    ($var:ident, $exp:expr) => (Statement::Let(stringify_from!($var), $exp))
+    ($var:ident, $exp:expr) => (unspanned_stmt!(StmtKind::Let(stringify_from!($var), $exp)))
+}
+
+macro_rules! stmt_return {
+    ($exp:expr) => (unspanned_stmt!(StmtKind::Return($exp)))
 }
 
 macro_rules! stmt_print_line {
-    ($exp:expr) => (Statement::PrintLine($exp))
+    ($exp:expr) => (unspanned_stmt!(StmtKind::PrintLine($exp)))
 }
 
 macro_rules! stmt_var_assign {
-    ($var:ident, $exp:expr) => (Statement::VarAssign(stringify_from!($var), $exp))
+    ($var:ident, $exp:expr) => (unspanned_stmt!(StmtKind::VarAssign(stringify_from!($var), $exp)))
 }
 
 macro_rules! stmt_void_call {
-    ($name:ident ($($arg:expr),*)) => (Statement::VoidCall(stringify_from!($name), vec![$($arg),*]))
+    ($name:ident ($($arg:expr),*)) => (unspanned_stmt!(StmtKind::VoidCall(var!($name), vec![$($arg),*])))
 }
 
 macro_rules! stmt_while {
-    ($clause:expr, { $($stmt:expr);* }) => (Statement::While($clause, vec![$($stmt),*]))
+    ($clause:expr, { $($stmt:expr);* }) => (unspanned_stmt!(StmtKind::While(None, $clause, vec![$($stmt),*])));
+    ($label:ident: $clause:expr, { $($stmt:expr);* }) =>
+        (unspanned_stmt!(StmtKind::While(Some(stringify_from!($label)), $clause, vec![$($stmt),*])))
 }
@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod test;
+
+use ast::{Expr, ExprKind, Statement, StmtKind};
+use grammar::{parse_Exp, parse_Program, parse_Stmt};
+use lalrpop_util;
+use token::{Error, Token, Tokenizer};
+
+pub type ParseError<'input> = lalrpop_util::ParseError<usize, Token<'input>, Error>;
+
+// Parses a program, recovering from syntax errors where possible. Returns whatever part of the
+// program could be parsed along with every syntax error that was found.
+pub fn parse_program(input: &str) -> (Vec<Statement>, Vec<ParseError>) {
+    let tokenizer = Tokenizer::new(input, 0);
+    let mut recovered = Vec::new();
+
+    let result = parse_Program(input, &mut recovered, tokenizer);
+    let mut errors: Vec<_> = recovered.into_iter().map(|r| r.error).collect();
+
+    match result {
+        Ok(program) => {
+            check_loops(&program, &mut Vec::new(), &mut errors);
+            (program, errors)
+        }
+        Err(e) => {
+            errors.push(e);
+            (Vec::new(), errors)
+        }
+    }
+}
+
+// Parses a single statement, recovering from syntax errors in the same way as `parse_program`.
+pub fn parse_stmt(input: &str) -> (Option<Statement>, Vec<ParseError>) {
+    let tokenizer = Tokenizer::new(input, 0);
+    let mut recovered = Vec::new();
+
+    let result = parse_Stmt(input, &mut recovered, tokenizer);
+    let mut errors: Vec<_> = recovered.into_iter().map(|r| r.error).collect();
+
+    match result {
+        Ok(stmt) => {
+            check_loops(&[stmt.clone()], &mut Vec::new(), &mut errors);
+            (Some(stmt), errors)
+        }
+        Err(e) => {
+            errors.push(e);
+            (None, errors)
+        }
+    }
+}
+
+// Parses a single expression, e.g. one given to an `Interpreter` to be evaluated.
+pub fn parse_exp(input: &str) -> Result<Expr, ParseError> {
+    let tokenizer = Tokenizer::new(input, 0);
+    let mut recovered = Vec::new();
+
+    let exp = try!(parse_Exp(input, &mut recovered, tokenizer));
+    let mut errors: Vec<_> = recovered.into_iter().map(|r| r.error).collect();
+    check_lambdas(&exp, &mut errors);
+
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(exp),
+    }
+}
+
+// Checks that every `break` and `continue` is inside a loop, and that any label they give belongs to
+// one of the loops they're in. `labels` has the label of each enclosing loop, innermost last.
+fn check_loops(stmts: &[Statement], labels: &mut Vec<Option<String>>, errors: &mut Vec<ParseError>) {
+    for stmt in stmts {
+        for exp in stmt_exps(stmt) {
+            check_lambdas(exp, errors);
+        }
+
+        match stmt.kind {
+            StmtKind::Break(ref label) | StmtKind::Continue(ref label) => {
+                let keyword = match stmt.kind {
+                    StmtKind::Break(_) => "break",
+                    _ => "continue",
+                };
+
+                let message = match *label {
+                    _ if labels.is_empty() => Some(format!("`{}` can only be used inside a loop", keyword)),
+                    Some(ref l) if !labels.contains(label) =>
+                        Some(format!("There's no loop labelled `{}` for `{} {}` to refer to", l, keyword, l)),
+                    _ => None,
+                };
+
+                if let Some(message) = message {
+                    errors.push(lalrpop_util::ParseError::User {
+                        error: Error { location: stmt.span.lo, message: message },
+                    });
+                }
+            }
+            StmtKind::For(ref label, _, _, ref block) | StmtKind::While(ref label, _, ref block) => {
+                labels.push(label.clone());
+                check_loops(block, labels, errors);
+                labels.pop();
+            }
+            StmtKind::If(_, ref block1, ref block2) => {
+                check_loops(block1, labels, errors);
+                check_loops(block2, labels, errors);
+            }
+            // Loops outside a function don't continue inside of it.
+            StmtKind::Defun(_, _, _, ref body) => check_loops(body, &mut Vec::new(), errors),
+            _ => (),
+        }
+    }
+}
+
+// Checks the bodies of any anonymous functions in an expression. Like named functions, they don't
+// continue the loops they're created in.
+fn check_lambdas(exp: &Expr, errors: &mut Vec<ParseError>) {
+    match exp.kind {
+        ExprKind::Lambda(_, _, ref body) => check_loops(body, &mut Vec::new(), errors),
+        ExprKind::Array(ref exps) | ExprKind::Interpolation(ref exps) => for e in exps {
+            check_lambdas(e, errors);
+        },
+        ExprKind::ArrayElement(_, ref index, ref indexes) => {
+            check_lambdas(index, errors);
+
+            for e in indexes {
+                check_lambdas(e, errors);
+            }
+        }
+        ExprKind::BinExp(ref e1, _, ref e2) | ExprKind::Range(ref e1, ref e2) => {
+            check_lambdas(e1, errors);
+            check_lambdas(e2, errors);
+        }
+        ExprKind::Call(ref func, ref args) => {
+            check_lambdas(func, errors);
+
+            for e in args {
+                check_lambdas(e, errors);
+            }
+        }
+        ExprKind::Keys(ref e) | ExprKind::Length(ref e) | ExprKind::Letters(ref e) | ExprKind::Not(ref e) |
+        ExprKind::ToFloat(ref e) | ExprKind::ToInt(ref e) | ExprKind::Values(ref e) => check_lambdas(e, errors),
+        ExprKind::Map(ref entries) => for &(ref key, ref val) in entries {
+            check_lambdas(key, errors);
+            check_lambdas(val, errors);
+        },
+        ExprKind::Step(ref start, ref end, ref step) => {
+            check_lambdas(start, errors);
+            check_lambdas(end, errors);
+            check_lambdas(step, errors);
+        }
+        ExprKind::ReadLine | ExprKind::Value(_) | ExprKind::Var(_) => (),
+    }
+}
+
+// Returns the expressions that are directly part of a statement, not counting those in its blocks.
+fn stmt_exps(stmt: &Statement) -> Vec<&Expr> {
+    match stmt.kind {
+        StmtKind::ArrayElemAssign(_, ref index, ref indexes, ref exp) => {
+            let mut exps = vec![index];
+            exps.extend(indexes);
+            exps.push(exp);
+            exps
+        }
+        StmtKind::Delete(_, ref index, ref indexes) => {
+            let mut exps = vec![index];
+            exps.extend(indexes);
+            exps
+        }
+        StmtKind::VoidCall(ref func, ref args) => {
+            let mut exps = vec![func];
+            exps.extend(args);
+            exps
+        }
+        StmtKind::For(_, _, ref exp, _) | StmtKind::If(ref exp, _, _) | StmtKind::Let(_, ref exp) |
+        StmtKind::Print(ref exp) | StmtKind::PrintLine(ref exp) | StmtKind::Return(ref exp) |
+        StmtKind::VarAssign(_, ref exp) | StmtKind::While(_, ref exp, _) => vec![exp],
+        StmtKind::Break(_) | StmtKind::Continue(_) | StmtKind::Defun(..) | StmtKind::Error => Vec::new(),
+    }
+}
@@ -0,0 +1,176 @@
+use ast::{Expr, ExprKind, StmtKind, Type, Value};
+use parser::{parse_program, parse_stmt};
+use token::{Token, Tokenizer};
+
+#[test]
+fn program_without_errors() {
+    let (program, errors) = parse_program("let x = 1;\nprint_line x;\n");
+
+    assert_eq!(2, program.len());
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn recover_at_semicolon() {
+    let (program, errors) = parse_program("let x = 1 2;\nlet y = ;\nprint_line x;\n");
+
+    assert_eq!(2, errors.len());
+    assert_eq!(3, program.len());
+
+    match (&program[0].kind, &program[1].kind, &program[2].kind) {
+        (&StmtKind::Error, &StmtKind::Error, &StmtKind::PrintLine(_)) => (),
+        _ => panic!("Expected two error statements followed by a `print_line`"),
+    }
+}
+
+#[test]
+fn recover_at_right_brace() {
+    let (program, errors) = parse_program("void f() {\n    print_line 1\n}\nlet x = ;\n");
+
+    assert_eq!(2, errors.len());
+    assert_eq!(2, program.len());
+}
+
+#[test]
+fn recover_at_end_of_program() {
+    let (program, errors) = parse_program("let x = 1;\nlet y = (");
+
+    assert_eq!(1, errors.len());
+    assert_eq!(1, program.len());
+
+    let (program, errors) = parse_program("let x = 1;\nif (x) {");
+
+    assert_eq!(1, errors.len());
+    assert_eq!(1, program.len());
+    assert!(match program[0].kind { StmtKind::Let(ref name, _) => name == "x", _ => false });
+}
+
+#[test]
+fn int_literal_too_big() {
+    let (_, errors) = parse_program("let x = 9223372036854775808;\nlet y = -9223372036854775808;\n");
+    assert_eq!(1, errors.len());
+}
+
+#[test]
+fn stmt_with_error() {
+    let (stmt, errors) = parse_stmt("let x = 1 2;");
+
+    assert_eq!(1, errors.len());
+    assert!(stmt.is_some());
+}
+
+#[test]
+fn break_and_continue_in_loops() {
+    let text = "outer: while (true) {\n    for x in [1, 2] {\n        continue outer;\n    }\n    break;\n}\n";
+    let (program, errors) = parse_program(text);
+
+    assert!(errors.is_empty());
+
+    match program[0].kind {
+        StmtKind::While(Some(ref label), _, _) => assert_eq!("outer", label),
+        _ => panic!("Expected a labelled `while` loop"),
+    }
+}
+
+#[test]
+fn break_outside_loop() {
+    let (_, errors) = parse_program("break;\nwhile (true) {\n    continue inner;\n}\nvoid f() {\n    while (true) {}\n    break;\n}\n");
+    assert_eq!(3, errors.len());
+
+    let (_, errors) = parse_stmt("continue;");
+    assert_eq!(1, errors.len());
+
+    // Anonymous functions don't continue the loops they're created in.
+    let (_, errors) = parse_program("while (true) {\n    let f = void () { break; };\n}\n");
+    assert_eq!(1, errors.len());
+}
+
+#[test]
+fn map_literal() {
+    let (program, errors) = parse_program("let m = {\"a\": 1, \"b\": {}};\n");
+
+    assert!(errors.is_empty());
+
+    match program[0].kind {
+        StmtKind::Let(_, Expr { kind: ExprKind::Map(ref entries), .. }) => assert_eq!(2, entries.len()),
+        _ => panic!("Expected a map literal"),
+    }
+}
+
+#[test]
+fn map_builtin_and_dictionary_type() {
+    let text = "let f = map;\nfor map in [1] { }\ndictionary counts(xs) { }\nlet g = dictionary (x) { return {}; };\n";
+    let (program, errors) = parse_program(text);
+
+    assert!(errors.is_empty());
+    assert!(match program[0].kind { StmtKind::Let(_, Expr { kind: ExprKind::Var(ref v), .. }) => v == "map", _ => false });
+    assert!(match program[2].kind { StmtKind::Defun(Type::Map, ..) => true, _ => false });
+    assert!(match program[3].kind {
+        StmtKind::Let(_, Expr { kind: ExprKind::Lambda(Type::Map, ..), .. }) => true,
+        _ => false,
+    });
+}
+
+#[test]
+fn comments_are_skipped() {
+    let text = "// The answer.\nlet x = 42; // Not 41.\n/* A /* nested */ comment */ print_line x / 2;\n";
+    let (program, errors) = parse_program(text);
+
+    assert!(errors.is_empty());
+    assert_eq!(2, program.len());
+}
+
+#[test]
+fn comments_kept_as_trivia() {
+    let text = "let x = 1; // one\n/* a /* b */ c */";
+    let tokens: Vec<_> = Tokenizer::with_trivia(text, 0).map(|t| t.unwrap().1).collect();
+
+    assert_eq!(vec![Token::Let, Token::Ident("x"), Token::Equal, Token::I64("1"), Token::Semicolon,
+                    Token::LineComment("// one"), Token::BlockComment("/* a /* b */ c */")], tokens);
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let (_, errors) = parse_program("let x = 1; /* /* */");
+
+    assert_eq!(1, errors.len());
+}
+
+#[test]
+fn string_escapes() {
+    let (stmt, errors) = parse_stmt(r#"print "a\nb\t\"c\" \\ \u{e9} \$";"#);
+    assert!(errors.is_empty());
+
+    match stmt.unwrap().kind {
+        StmtKind::Print(Expr { kind: ExprKind::Value(Value::Str(ref s)), .. }) =>
+            assert_eq!("a\nb\t\"c\" \\ \u{e9} $", s),
+        _ => panic!("Expected a `print` of a string literal"),
+    }
+}
+
+#[test]
+fn invalid_escapes() {
+    assert_eq!(1, parse_stmt(r#"print "\q";"#).1.len());
+    assert_eq!(1, parse_stmt(r#"print "\u{110000}";"#).1.len());
+    assert_eq!(1, parse_stmt(r#"print "\u{41";"#).1.len());
+}
+
+#[test]
+fn interpolation_tokens() {
+    let text = r#""a ${f("b")} c ${ {} } d""#;
+    let tokens: Vec<_> = Tokenizer::new(text, 0).map(|t| t.unwrap().1).collect();
+
+    assert_eq!(vec![Token::StringStart("a "), Token::Ident("f"), Token::LeftParen, Token::StringLiteral("b"),
+                    Token::RightParen, Token::StringMiddle(" c "), Token::LeftBrace, Token::RightBrace,
+                    Token::StringEnd(" d")], tokens);
+}
+
+#[test]
+fn number_tokens() {
+    let text = "1 1.5 -2.25 1e-3 2E+4 4e";
+    let tokens: Vec<_> = Tokenizer::new(text, 0).map(|t| t.unwrap().1).collect();
+
+    assert_eq!(vec![Token::I64("1"), Token::F64("1.5"), Token::F64("-2.25"), Token::F64("1e-3"),
+                    Token::F64("2E+4"), Token::I64("4"), Token::Ident("e")],
+               tokens);
+}
@@ -0,0 +1,31 @@
+use std::net::TcpListener;
+
+// Starts listening on the address given by the command line arguments of one of the server programs,
+// `[--host HOST] [--port PORT]`, or returns the message to show if they're wrong or the address can't
+// be listened on. `name` is the name of the program, for its usage message.
+pub fn listen_from_args<I: Iterator<Item = String>>(name: &str, args: I,
+                                                    default_port: u16) -> Result<TcpListener, String> {
+    let (host, port) = try!(parse_address_args(name, args, default_port));
+
+    TcpListener::bind((&host[..], port)).map_err(|e| format!("Unable to listen on {}:{}: {}", host, port, e))
+}
+
+// Returns the host and port given by the arguments, defaulting to `localhost` and `default_port`.
+pub fn parse_address_args<I: Iterator<Item = String>>(name: &str, mut args: I,
+                                                      default_port: u16) -> Result<(String, u16), String> {
+    let mut host = String::from("localhost");
+    let mut port = default_port;
+
+    while let Some(arg) = args.next() {
+        match (&arg[..], args.next()) {
+            ("--host", Some(value)) => host = value,
+            ("--port", Some(value)) => port = match value.parse() {
+                Ok(port) => port,
+                Err(_) => return Err(format!("`{}` isn't a valid port", value)),
+            },
+            _ => return Err(format!("Usage: {} [--host HOST] [--port PORT]", name)),
+        }
+    }
+
+    Ok((host, port))
+}
@@ -0,0 +1,19 @@
+#[cfg(test)]
+mod test;
+
+mod args;
+mod protocol;
+mod tcp;
+mod websocket;
+
+pub use self::args::listen_from_args;
+pub use self::protocol::{event_to_json, protocol_error_json, Request};
+pub use self::tcp::serve_tcp;
+pub use self::websocket::serve_websocket;
+
+// How many seconds a client has to send its program after connecting.
+const FIRST_MESSAGE_TIMEOUT_SECS: u64 = 10;
+
+// How many seconds sending a message to a client can take, so that a client that stops reading them
+// doesn't keep its program running.
+const WRITE_TIMEOUT_SECS: u64 = 60;
@@ -0,0 +1,73 @@
+use serde_json::{self, Value as Json};
+
+use stream::Event;
+
+// A message from a client. Each message is a JSON object on its own line with a `type` field:
+//
+//     {"type": "run", "program": "print_line read_line();"}
+//     {"type": "input", "line": "hello"}
+//     {"type": "cancel"}
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    // Runs a program. It must be the first message, and only one program is run per connection.
+    Run(String),
+    // Gives the program a line of input.
+    Input(String),
+    // Stops the program.
+    Cancel,
+}
+
+impl Request {
+    pub fn from_json(s: &str) -> Result<Self, String> {
+        let json: Json = try!(serde_json::from_str(s).map_err(|e| format!("The message isn't valid JSON: {}", e)));
+
+        match json["type"].as_str() {
+            Some("run") => match json["program"].as_str() {
+                Some(program) => Ok(Request::Run(String::from(program))),
+                None => Err(String::from("A `run` message needs a `program` string")),
+            },
+            Some("input") => match json["line"].as_str() {
+                Some(line) => Ok(Request::Input(String::from(line))),
+                None => Err(String::from("An `input` message needs a `line` string")),
+            },
+            Some("cancel") => Ok(Request::Cancel),
+            Some(other) => Err(format!("`{}` isn't a type of message", other)),
+            None => Err(String::from("The message needs a `type` string")),
+        }
+    }
+}
+
+// Encodes an event as a JSON object, e.g. `{"type": "output", "text": "hello"}`. `text` is the
+// program, used to give the line and column of errors along with their span.
+pub fn event_to_json(event: &Event, text: &str) -> String {
+    let json = match *event {
+        Event::Cancelled => json!({ "type": "cancelled" }),
+        Event::Error { ref kind, ref message, span, ref notes } => json!({
+            "type": "error",
+            "kind": kind,
+            "message": message,
+            "span": span.map(|s| json!({ "lo": s.lo, "hi": s.hi })),
+            "line": span.map(|s| s.line_col(text).0),
+            "column": span.map(|s| s.line_col(text).1),
+            "notes": notes,
+        }),
+        Event::Finished => json!({ "type": "finished" }),
+        Event::NeedsInput => json!({ "type": "needs_input" }),
+        Event::Output(ref s) => json!({ "type": "output", "text": s }),
+    };
+
+    json.to_string()
+}
+
+// Encodes an error in a message from a client in the same way as an error in a program.
+pub fn protocol_error_json(message: &str) -> String {
+    json!({
+        "type": "error",
+        "kind": "ProtocolError",
+        "message": message,
+        "span": null,
+        "line": null,
+        "column": null,
+        "notes": [],
+    }).to_string()
+}
@@ -0,0 +1,211 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use config::Config;
+use run_program_with_stream_and_config;
+use server::{FIRST_MESSAGE_TIMEOUT_SECS, WRITE_TIMEOUT_SECS};
+use server::protocol::{event_to_json, protocol_error_json, Request};
+use stream::{Stream, Timeout};
+
+// The most bytes a message from a client can take up, including its newline, so that a client can't
+// use up the server's memory by never ending a line.
+pub const MAX_LINE: u64 = 1 << 20;
+
+// The most clients that can be served at once. Each one takes a few threads, so any more are turned
+// away.
+pub const MAX_SESSIONS: usize = 64;
+
+// Serves clients connecting to a listener until it stops accepting connections. Each client is
+// served on its own threads, so a slow or stuck program doesn't hold up the others. The messages
+// sent each way are described by `Request` and `event_to_json`.
+pub fn serve_tcp(listener: TcpListener, config: Config) {
+    let sessions = Arc::new(AtomicUsize::new(0));
+
+    for socket in listener.incoming() {
+        // A failed connection only affects that client, so keep serving the others.
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+
+        let session = match Session::start(&sessions) {
+            Some(session) => session,
+            None => {
+                let writer = Mutex::new(socket);
+                let _ = send(&writer, &protocol_error_json("The server is too busy to run any more programs"));
+                continue;
+            }
+        };
+
+        let config = config.clone();
+        thread::spawn(move || {
+            let _session = session;
+            let _ = handle_client(socket, config);
+        });
+    }
+}
+
+// A client being served, which counts toward `MAX_SESSIONS` until it's dropped.
+struct Session(Arc<AtomicUsize>);
+
+impl Session {
+    // Starts a session, unless there are already `MAX_SESSIONS` of them.
+    fn start(sessions: &Arc<AtomicUsize>) -> Option<Self> {
+        if sessions.fetch_add(1, Ordering::SeqCst) < MAX_SESSIONS {
+            Some(Session(sessions.clone()))
+        } else {
+            sessions.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Runs the program sent by a client, sending it the program's events and passing on its input.
+fn handle_client(socket: TcpStream, config: Config) -> io::Result<()> {
+    try!(socket.set_read_timeout(Some(Duration::from_secs(FIRST_MESSAGE_TIMEOUT_SECS))));
+    try!(socket.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS))));
+    let mut reader = BufReader::new(try!(socket.try_clone()));
+    // Errors in the client's messages are sent from the thread reading them, so the socket is
+    // shared between it and this one.
+    let writer = Arc::new(Mutex::new(socket));
+    let mut line = String::new();
+
+    match read_message(&mut reader, &mut line) {
+        Ok(true) => (),
+        Ok(false) => return Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidData =>
+            return send(&writer, &protocol_error_json(&e.to_string())),
+        Err(e) => return Err(e),
+    }
+
+    // Input can be sent at any time while the program is running.
+    try!(reader.get_ref().set_read_timeout(None));
+
+    let program = match Request::from_json(&line) {
+        Ok(Request::Run(program)) => program,
+        Ok(_) => return send(&writer, &protocol_error_json("The first message has to be a `run` message")),
+        Err(e) => return send(&writer, &protocol_error_json(&e)),
+    };
+
+    let input_timeout = config.limits.input_timeout;
+    let (stream, handle) = run_program_with_stream_and_config(&program, config);
+
+    {
+        let stream = stream.clone();
+        let writer = writer.clone();
+        thread::spawn(move || forward_requests(reader, &stream, &writer));
+    }
+
+    loop {
+        let event = match input_timeout {
+            None => stream.get_event(),
+            Some(timeout) => match stream.get_event_timeout(timeout) {
+                Ok(event) => event,
+                // The program sends a `needs_input` event when it starts waiting for input, so if it's
+                // still waiting after a whole timeout without events, the client has stopped answering.
+                Err(Timeout) => {
+                    if stream.is_waiting_for_input() {
+                        let message = "No input was sent in time, so the program was cancelled";
+                        let _ = send(&writer, &protocol_error_json(message));
+                        stream.cancel();
+                    }
+
+                    continue;
+                }
+            },
+        };
+
+        let event = match event {
+            Some(event) => event,
+            None => break,
+        };
+
+        // The client has gone, so there's no point running the program any more.
+        if let Err(e) = send(&writer, &event_to_json(&event, &program)) {
+            let _ = handle.cancel_and_join();
+            return Err(e);
+        }
+    }
+
+    let _ = handle.join();
+
+    // Closing the connection also ends the thread reading from it.
+    let socket = writer.lock().unwrap();
+    socket.shutdown(Shutdown::Both)
+}
+
+// Passes the messages a client sends while its program is running on to the program.
+fn forward_requests(mut reader: BufReader<TcpStream>, stream: &Stream, writer: &Mutex<TcpStream>) {
+    let mut line = String::new();
+
+    loop {
+        match read_message(&mut reader, &mut line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            // The rest of a message that's too long can't be told apart from the next message, so stop
+            // reading after one.
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                let _ = send(writer, &protocol_error_json(&e.to_string()));
+                break;
+            }
+            Err(_) => break,
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let error = match Request::from_json(&line) {
+            Ok(Request::Input(input)) => {
+                stream.write_input(&input);
+                continue;
+            }
+            Ok(Request::Cancel) => {
+                stream.cancel();
+                continue;
+            }
+            Ok(Request::Run(_)) => String::from("Only one program can be run per connection"),
+            Err(e) => e,
+        };
+
+        if send(writer, &protocol_error_json(&error)).is_err() {
+            break;
+        }
+    }
+
+    // The client won't send any more input, so `read_line` gets empty strings from now on rather than
+    // waiting forever.
+    stream.close_input();
+}
+
+// Reads a message from the client into `line`, returning `false` once the client has stopped sending
+// them. A message longer than `MAX_LINE` fails with an `InvalidData` error, as does one that isn't
+// UTF-8.
+fn read_message<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    let len = try!(reader.by_ref().take(MAX_LINE).read_line(line));
+
+    if len as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("Messages can't be longer than {} bytes", MAX_LINE)));
+    }
+
+    Ok(len > 0)
+}
+
+// Sends a message to the client on its own line.
+fn send(writer: &Mutex<TcpStream>, json: &str) -> io::Result<()> {
+    let mut socket = writer.lock().unwrap();
+    try!(writeln!(socket, "{}", json));
+    socket.flush()
+}
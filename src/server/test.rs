@@ -0,0 +1,299 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{self, Value as Json};
+use tungstenite::{self, Message, WebSocket};
+use url::Url;
+
+use config::{Config, Limits};
+use server::{serve_tcp, serve_websocket, Request};
+use server::args::parse_address_args;
+use server::tcp::{MAX_LINE, MAX_SESSIONS};
+
+// Starts a server on a free port on the loopback interface.
+fn start_server() -> SocketAddr {
+    start_server_with_config(Config::default())
+}
+
+fn start_server_with_config(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || serve_tcp(listener, config));
+    addr
+}
+
+fn start_websocket_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || serve_websocket(listener, Config::default()));
+    addr
+}
+
+fn connect_websocket(addr: SocketAddr) -> WebSocket<TcpStream> {
+    let url = Url::parse(&format!("ws://{}/", addr)).unwrap();
+    tungstenite::client(url, TcpStream::connect(addr).unwrap()).unwrap().0
+}
+
+fn send_frame(websocket: &mut WebSocket<TcpStream>, json: Json) {
+    websocket.write_message(Message::Text(json.to_string())).unwrap();
+}
+
+fn receive_frame(websocket: &mut WebSocket<TcpStream>) -> Json {
+    match websocket.read_message().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        message => panic!("expected a text message, got {:?}", message),
+    }
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let socket = TcpStream::connect(addr).unwrap();
+        Client { reader: BufReader::new(socket.try_clone().unwrap()), writer: socket }
+    }
+
+    fn send(&mut self, json: Json) {
+        writeln!(self.writer, "{}", json).unwrap();
+    }
+
+    // Returns the next message from the server, or `None` if it has closed the connection.
+    fn receive(&mut self) -> Option<Json> {
+        let mut line = String::new();
+
+        match self.reader.read_line(&mut line).unwrap() {
+            0 => None,
+            _ => Some(serde_json::from_str(&line).unwrap()),
+        }
+    }
+
+    // Returns the messages from the server up to and including `finished`.
+    fn receive_until_finished(&mut self) -> Vec<Json> {
+        let mut messages = Vec::new();
+
+        while let Some(message) = self.receive() {
+            let finished = message["type"] == "finished";
+            messages.push(message);
+
+            if finished {
+                break;
+            }
+        }
+
+        messages
+    }
+}
+
+#[test]
+fn parse_requests() {
+    assert_eq!(Ok(Request::Run(String::from("print 1;"))),
+        Request::from_json(r#"{"type": "run", "program": "print 1;"}"#));
+    assert_eq!(Ok(Request::Input(String::from("x"))), Request::from_json(r#"{"type": "input", "line": "x"}"#));
+    assert_eq!(Ok(Request::Cancel), Request::from_json(r#"{"type": "cancel"}"#));
+
+    assert!(Request::from_json("print 1;").is_err());
+    assert!(Request::from_json(r#"{"type": "run"}"#).is_err());
+    assert!(Request::from_json(r#"{"type": "jump"}"#).is_err());
+}
+
+#[test]
+fn address_args() {
+    let parse = |args: &[&str]| parse_address_args("listener", args.iter().map(|a| String::from(*a)), 7777);
+
+    assert_eq!(Ok((String::from("localhost"), 7777)), parse(&[]));
+    assert_eq!(Ok((String::from("0.0.0.0"), 80)), parse(&["--port", "80", "--host", "0.0.0.0"]));
+    assert_eq!(Err(String::from("`http` isn't a valid port")), parse(&["--port", "http"]));
+    assert_eq!(Err(String::from("Usage: listener [--host HOST] [--port PORT]")), parse(&["--host"]));
+}
+
+#[test]
+fn run_program() {
+    let mut client = Client::connect(start_server());
+    client.send(json!({ "type": "run", "program": "print_line 1 + 2;\nprint \"done\";" }));
+
+    assert_eq!(vec![
+        json!({ "type": "output", "text": "3\n" }),
+        json!({ "type": "output", "text": "done" }),
+        json!({ "type": "finished" }),
+    ], client.receive_until_finished());
+
+    // The server closes the connection once the program has finished.
+    assert_eq!(None, client.receive());
+}
+
+#[test]
+fn input() {
+    let mut client = Client::connect(start_server());
+    client.send(json!({ "type": "run", "program": "let name = read_line();\nprint \"hi ${name}\";" }));
+
+    assert_eq!(Some(json!({ "type": "needs_input" })), client.receive());
+    client.send(json!({ "type": "input", "line": "pal" }));
+
+    assert_eq!(vec![json!({ "type": "output", "text": "hi pal" }), json!({ "type": "finished" })],
+        client.receive_until_finished());
+}
+
+#[test]
+fn errors() {
+    let addr = start_server();
+
+    let mut client = Client::connect(addr);
+    client.send(json!({ "type": "run", "program": "print 1;\nlet x = 1 + true;" }));
+    let messages = client.receive_until_finished();
+
+    assert_eq!(3, messages.len());
+    assert_eq!("error", messages[1]["type"]);
+    assert_eq!("TypeError", messages[1]["kind"]);
+    assert_eq!(json!({ "lo": 17, "hi": 25 }), messages[1]["span"]);
+    assert_eq!(2, messages[1]["line"]);
+    assert_eq!(9, messages[1]["column"]);
+
+    let mut client = Client::connect(addr);
+    client.send(json!({ "type": "input", "line": "too soon" }));
+    let message = client.receive().unwrap();
+
+    assert_eq!("ProtocolError", message["kind"]);
+    assert_eq!(None, client.receive());
+}
+
+#[test]
+fn message_too_long() {
+    // The whole message is sent so that the server doesn't close the connection with some of it unread.
+    let mut client = Client::connect(start_server());
+    client.writer.write_all(&vec![b' '; MAX_LINE as usize]).unwrap();
+    let message = client.receive().unwrap();
+
+    assert_eq!("ProtocolError", message["kind"]);
+    assert_eq!(None, client.receive());
+}
+
+#[test]
+fn input_timeout() {
+    let config = Config { limits: Limits { input_timeout: Some(Duration::from_millis(100)), ..Limits::default() },
+                          ..Config::default() };
+    let mut client = Client::connect(start_server_with_config(config));
+    client.send(json!({ "type": "run", "program": "let x = read_line();\nprint x;" }));
+
+    assert_eq!(Some(json!({ "type": "needs_input" })), client.receive());
+    assert_eq!("ProtocolError", client.receive().unwrap()["kind"]);
+    assert_eq!(vec![json!({ "type": "cancelled" }), json!({ "type": "finished" })], client.receive_until_finished());
+}
+
+#[test]
+fn too_many_sessions() {
+    let addr = start_server();
+    let mut clients: Vec<_> = (0..MAX_SESSIONS).map(|_| Client::connect(addr)).collect();
+
+    // Each of these sessions is kept going by its program waiting for input.
+    for client in &mut clients {
+        client.send(json!({ "type": "run", "program": "let x = read_line();" }));
+        assert_eq!(Some(json!({ "type": "needs_input" })), client.receive());
+    }
+
+    let mut client = Client::connect(addr);
+    assert_eq!("ProtocolError", client.receive().unwrap()["kind"]);
+    assert_eq!(None, client.receive());
+}
+
+#[test]
+fn cancel() {
+    let mut client = Client::connect(start_server());
+    client.send(json!({ "type": "run", "program": "while (true) { }" }));
+    client.send(json!({ "type": "cancel" }));
+
+    assert_eq!(vec![json!({ "type": "cancelled" }), json!({ "type": "finished" })], client.receive_until_finished());
+}
+
+#[test]
+fn concurrent_clients() {
+    let addr = start_server();
+
+    // Every client connects and starts a program waiting for input before any of them sends it, so
+    // they all have to be served at the same time.
+    let mut clients: Vec<_> = (0..10).map(|_| Client::connect(addr)).collect();
+
+    for client in &mut clients {
+        client.send(json!({ "type": "run", "program": "print_line to_int(read_line()) * 2;" }));
+        assert_eq!(Some(json!({ "type": "needs_input" })), client.receive());
+    }
+
+    for (i, client) in clients.iter_mut().enumerate() {
+        client.send(json!({ "type": "input", "line": format!("{}", i) }));
+    }
+
+    for (i, client) in clients.iter_mut().enumerate() {
+        assert_eq!(vec![json!({ "type": "output", "text": format!("{}\n", i * 2) }), json!({ "type": "finished" })],
+            client.receive_until_finished());
+    }
+}
+
+#[test]
+fn playground_page() {
+    let mut socket = TcpStream::connect(start_websocket_server()).unwrap();
+    write!(socket, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    socket.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("<title>pal playground</title>"));
+}
+
+#[test]
+fn websocket_session() {
+    let mut websocket = connect_websocket(start_websocket_server());
+    send_frame(&mut websocket, json!({ "type": "run", "program": "print \"name? \";\nprint_line \"hi ${read_line()}\";" }));
+
+    assert_eq!(json!({ "type": "output", "text": "name? " }), receive_frame(&mut websocket));
+    assert_eq!(json!({ "type": "needs_input" }), receive_frame(&mut websocket));
+
+    send_frame(&mut websocket, json!({ "type": "input", "line": "pal" }));
+
+    assert_eq!(json!({ "type": "output", "text": "hi pal\n" }), receive_frame(&mut websocket));
+    assert_eq!(json!({ "type": "finished" }), receive_frame(&mut websocket));
+
+    // The server closes the connection once the program has finished.
+    assert!(match websocket.read_message() { Ok(Message::Close(_)) => true, _ => false });
+}
+
+#[test]
+fn idle_websocket_connection() {
+    // A client that never sends a request is disconnected rather than keeping a thread waiting.
+    let mut socket = TcpStream::connect(start_websocket_server()).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    let mut response = Vec::new();
+    socket.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+}
+
+#[test]
+fn idle_websocket_after_upgrade() {
+    // A client that upgrades the connection but never sends a program is also disconnected.
+    let mut websocket = connect_websocket(start_websocket_server());
+    websocket.get_ref().set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+
+    assert!(match websocket.read_message() {
+        Ok(Message::Close(_)) => true,
+        Err(tungstenite::Error::Io(ref e)) => e.kind() != io::ErrorKind::WouldBlock && e.kind() != io::ErrorKind::TimedOut,
+        Err(_) => true,
+        Ok(_) => false,
+    });
+}
+
+#[test]
+fn websocket_cancel() {
+    let mut websocket = connect_websocket(start_websocket_server());
+    send_frame(&mut websocket, json!({ "type": "run", "program": "while (true) { }" }));
+    send_frame(&mut websocket, json!({ "type": "cancel" }));
+
+    assert_eq!(json!({ "type": "cancelled" }), receive_frame(&mut websocket));
+    assert_eq!(json!({ "type": "finished" }), receive_frame(&mut websocket));
+}
@@ -0,0 +1,273 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use libc;
+use tungstenite::{self, Message, WebSocket};
+
+use config::Config;
+use run_program_with_stream_and_config;
+use server::{FIRST_MESSAGE_TIMEOUT_SECS, WRITE_TIMEOUT_SECS};
+use server::protocol::{event_to_json, protocol_error_json, Request};
+use stream::{Event, Stream};
+
+// The page served to browsers, which runs programs through a WebSocket to the same address.
+const PLAYGROUND: &'static str = include_str!("playground.html");
+
+// How many milliseconds to wait between checks for the rest of the head of an HTTP request.
+const POLL_INTERVAL_MS: u64 = 10;
+
+// How many milliseconds a client has to send the head of its request.
+const REQUEST_TIMEOUT_MS: u64 = 1000;
+
+// Serves the playground page and pal sessions over WebSocket until the listener stops accepting
+// connections. Each WebSocket connection runs one program, using the same JSON messages as
+// `serve_tcp`, with one message per text frame. Any other HTTP request gets the playground page.
+pub fn serve_websocket(listener: TcpListener, config: Config) {
+    for socket in listener.incoming() {
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+
+        let config = config.clone();
+        thread::spawn(move || {
+            let _ = handle_client(socket, config);
+        });
+    }
+}
+
+// The connection to a browser. Reads block until the session has started, and after that only
+// return what has already arrived, so that the session's thread can wait for both the browser and
+// the program, and do all of the reading and writing through one `WebSocket`.
+struct Connection {
+    socket: TcpStream,
+    blocking: bool,
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.blocking {
+            return self.socket.read(buf);
+        }
+
+        let len = unsafe {
+            libc::recv(self.socket.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), libc::MSG_DONTWAIT)
+        };
+
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+fn handle_client(mut socket: TcpStream, config: Config) -> io::Result<()> {
+    let head = try!(peek_request_head(&socket));
+
+    if !String::from_utf8_lossy(&head).to_lowercase().contains("upgrade: websocket") {
+        // Read the request so that closing the connection doesn't reset it before the browser has
+        // read the response.
+        let mut request = vec![0; head.len()];
+        try!(socket.read_exact(&mut request));
+
+        return write!(socket, "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                               Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                      PLAYGROUND.len(), PLAYGROUND);
+    }
+
+    // The handshake and the first message have to arrive in time, so that a client that connects and
+    // then goes quiet doesn't keep a thread waiting forever.
+    try!(socket.set_read_timeout(Some(Duration::from_secs(FIRST_MESSAGE_TIMEOUT_SECS))));
+    try!(socket.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS))));
+
+    let mut websocket = match tungstenite::accept(Connection { socket: socket, blocking: true }) {
+        Ok(websocket) => websocket,
+        Err(_) => return Ok(()),
+    };
+
+    // The first message has to start the program.
+    let program = match websocket.read_message() {
+        Ok(Message::Text(text)) => match Request::from_json(&text) {
+            Ok(Request::Run(program)) => program,
+            Ok(_) => return send(&mut websocket, &protocol_error_json("The first message has to be a `run` message")),
+            Err(e) => return send(&mut websocket, &protocol_error_json(&e)),
+        },
+        _ => return Ok(()),
+    };
+
+    let input_timeout = config.limits.input_timeout;
+    let (stream, handle) = run_program_with_stream_and_config(&program, config);
+
+    // The program's events are passed on from another thread as they arrive, which writes to
+    // `wake` so that this thread stops waiting for the browser and sends them.
+    let (waker, wake) = try!(UnixStream::pair());
+    try!(wake.set_nonblocking(true));
+    let (sender, events) = mpsc::channel();
+    let forwarder = {
+        let stream = stream.clone();
+        thread::spawn(move || forward_events(&stream, &sender, waker))
+    };
+
+    websocket.get_mut().blocking = false;
+    let result = run_session(&mut websocket, &program, &stream, &events, wake, input_timeout);
+
+    // Either the program has finished or the browser has gone, in which case there's no point
+    // running the program any more.
+    let _ = handle.cancel_and_join();
+    let _ = forwarder.join();
+    let _ = websocket.close(None);
+    let _ = websocket.write_pending();
+    result
+}
+
+// Passes the events of a program on to `run_session` until it finishes.
+fn forward_events(stream: &Stream, sender: &Sender<Event>, mut waker: UnixStream) {
+    while let Some(event) = stream.get_event() {
+        // The session has ended if these fail, but the events are still taken until the program
+        // finishes.
+        let _ = sender.send(event);
+        let _ = waker.write_all(&[0]);
+    }
+}
+
+// Sends the events of a program to the browser and passes on its messages until the program
+// finishes or the browser goes away. If the program has waited for input for longer than
+// `input_timeout`, it's cancelled.
+fn run_session(websocket: &mut WebSocket<Connection>, program: &str, stream: &Stream,
+               events: &mpsc::Receiver<Event>, mut wake: UnixStream,
+               input_timeout: Option<Duration>) -> io::Result<()> {
+    loop {
+        let fds = [websocket.get_ref().socket.as_raw_fd(), wake.as_raw_fd()];
+
+        // The program sends a `needs_input` event when it starts waiting for input, so if it's
+        // still waiting after a whole timeout without anything happening, the browser has stopped
+        // answering.
+        if !try!(wait_readable(&fds, input_timeout)) && stream.is_waiting_for_input() {
+            try!(send(websocket, &protocol_error_json("No input was sent in time, so the program was cancelled")));
+            stream.cancel();
+        }
+
+        let mut buf = [0; 64];
+        while let Ok(len) = wake.read(&mut buf) {
+            if len == 0 {
+                break;
+            }
+        }
+
+        while let Ok(event) = events.try_recv() {
+            try!(send(websocket, &event_to_json(&event, program)));
+
+            if let Event::Finished = event {
+                return Ok(());
+            }
+        }
+
+        loop {
+            let error = match websocket.read_message() {
+                Ok(Message::Text(text)) => match Request::from_json(&text) {
+                    Ok(Request::Input(input)) => {
+                        stream.write_input(&input);
+                        continue;
+                    }
+                    Ok(Request::Cancel) => {
+                        stream.cancel();
+                        continue;
+                    }
+                    Ok(Request::Run(_)) => String::from("Only one program can be run per connection"),
+                    Err(e) => e,
+                },
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => continue,
+                // Everything the browser has sent so far has been read.
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(to_io_error(e)),
+            };
+
+            try!(send(websocket, &protocol_error_json(&error)));
+        }
+    }
+}
+
+// Waits until one of the file descriptors has something to read or has been closed, returning
+// `false` if `timeout` passes first.
+fn wait_readable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<bool> {
+    let mut pollfds: Vec<_> = fds.iter().map(|&fd| libc::pollfd { fd: fd, events: libc::POLLIN, revents: 0 }).collect();
+    let timeout_ms = match timeout {
+        Some(timeout) => (timeout.as_secs() * 1000 + u64::from(timeout.subsec_nanos() / 1_000_000)) as libc::c_int,
+        None => -1,
+    };
+
+    loop {
+        match unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms) } {
+            n if n >= 0 => return Ok(n > 0),
+            _ => {
+                let e = io::Error::last_os_error();
+
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+// Waits for the head of an HTTP request (up to the blank line after the headers) without reading
+// it, so that the WebSocket handshake can still read it if it's an upgrade request.
+fn peek_request_head(socket: &TcpStream) -> io::Result<Vec<u8>> {
+    let mut buf = [0; 8192];
+
+    // Give up on requests that take more than a second or so to arrive, including from clients that
+    // never send anything.
+    try!(socket.set_read_timeout(Some(Duration::from_millis(REQUEST_TIMEOUT_MS))));
+
+    for _ in 0..REQUEST_TIMEOUT_MS / POLL_INTERVAL_MS {
+        let len = match socket.peek(&mut buf) {
+            Ok(len) => len,
+            Err(ref e) if is_timeout(e) => break,
+            Err(e) => return Err(e),
+        };
+
+        if let Some(end) = buf[..len].windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(buf[..end + 4].to_vec());
+        }
+
+        if len == 0 || len == buf.len() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "The request is incomplete or too long"))
+}
+
+fn send(websocket: &mut WebSocket<Connection>, json: &str) -> io::Result<()> {
+    websocket.write_message(Message::Text(String::from(json))).map_err(to_io_error)
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+fn to_io_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, format!("{}", e)),
+    }
+}
@@ -1,34 +1,127 @@
-mod function;
 mod scope;
 
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::result;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use self::function::Function;
 use self::scope::Scope;
 
-use ast::{Expr, Statement, Type, Value};
-use error::{Error, Result};
-use stream::Stream;
+use ast::{Expr, Function, FunctionKind, NativeFn, Statement, Type, Value};
+use console::Console;
+use config::Config;
+use eval::{call_builtin, Flow};
+use error::{Error, ErrorType, PalError, PalResult, Result};
 
 pub struct State {
     // The global scope
     global: Scope,
-    // The current evaluation scope
-    current: Option<Scope>,
-    // Maps function names to functions.
-    functions: HashMap<String, Function>,
+    // The local scopes of the code currently running, one list for each function being called and
+    // one for the top level of the program, innermost last. Each list has a scope for the
+    // parameters of the function and for each block being run within it. Code can only see the
+    // scopes of its own function and the global one, not those of its callers.
+    frames: Vec<Vec<Scope>>,
+    // Maps function names to the functions defined with them.
+    functions: HashMap<String, Arc<Function>>,
+    // Options given when the program was started.
+    config: Config,
+    // The number of statements run since the program started, and when it started, not counting
+    // time spent waiting for input, to check the program against the limits in `config`.
+    statements: u64,
+    started: Instant,
 }
 
 impl State {
     pub fn new() -> Self {
-        State { global: Scope::new(None), current: None, functions: HashMap::new() }
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        State {
+            global: Scope::new(),
+            frames: vec![Vec::new()],
+            functions: HashMap::new(),
+            config: config,
+            statements: 0,
+            started: Instant::now(),
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // Resets the statement count and the timer used for the limits in the config, so that code run
+    // in the same state later, e.g. the next line in the REPL, gets the full limits again.
+    pub fn start_run(&mut self) {
+        self.statements = 0;
+        self.started = Instant::now();
+    }
+
+    // Leaves time the program spent waiting, e.g. for input in `read_line`, out of the time it's run
+    // for, so that a slow user doesn't make it go over its timeout.
+    pub fn exclude_wait(&mut self, waited: Duration) {
+        self.started += waited;
+    }
+
+    // Counts a statement being run, returning an error if the program has run too many statements or
+    // for too long.
+    pub fn count_statement(&mut self) -> Result<()> {
+        self.statements += 1;
+
+        if let Some(max) = self.config.limits.max_statements {
+            if self.statements > max {
+                return Error::resource_limit_error(
+                    &format!("The program has run more than {} statements, which is the most it can run", max));
+            }
+        }
+
+        if let Some(timeout) = self.config.limits.timeout {
+            if self.started.elapsed() > timeout {
+                return Error::resource_limit_error(
+                    &format!("The program has run for longer than {}.{:03} seconds, which is the longest it can run",
+                        timeout.as_secs(), timeout.subsec_nanos() / 1_000_000));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks that an array, map or string isn't longer than the limit in the config. `source` is the
+    // expression that produced it, for the error message.
+    pub fn check_len(&self, val: &Value, source: &Display) -> Result<()> {
+        let max = match self.config.limits.max_len {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let (len, unit) = match *val {
+            Value::Array(ref vec) => (vec.len(), "elements"),
+            Value::Map(ref map) => (map.len(), "entries"),
+            // A string never has more characters than bytes, so only long ones need to be counted.
+            Value::Str(ref s) if s.len() > max => (s.chars().count(), "characters"),
+            _ => return Ok(()),
+        };
+
+        self.check_new_len(len, unit, source)
+    }
+
+    // Checks that an array, map or string of length `len` isn't longer than the limit in the config,
+    // e.g. before creating an array with `range`. `unit` is what the length counts.
+    pub fn check_new_len(&self, len: usize, unit: &str, source: &Display) -> Result<()> {
+        match self.config.limits.max_len {
+            Some(max) if len > max => Error::resource_limit_error(
+                &format!("`{}` has {} {}, but the most anything can have is {}", source, len, unit, max)),
+            _ => Ok(()),
+        }
     }
 
     // Assigns a value to a variable, returning an error if the variable is not already defined.
     pub fn assign(&mut self, var: &str, val: Value) -> Result<()> {
-        // Check current scope
-        if let Some(ref mut scope) = self.current {
+        // Check the scopes of the current function, innermost first
+        for scope in self.frames.last_mut().unwrap().iter_mut().rev() {
             if scope.contains_var(var) {
                 return Ok(scope.assign(var, val));
             }
@@ -43,73 +136,137 @@ impl State {
             &format!("The variable `{}` has not been declared, so it can't have a value assigned to it", var))
     }
 
-    // Evaluates a function given its name and arguments.
-    pub fn call_function(&mut self, name: &str, args: &[Expr], stream: Option<Arc<Stream>>) -> Result<Option<Value>> {
+    // Evaluates a function given its name and arguments. The name can be that of a variable holding a
+    // function or of a function defined with it.
+    pub fn call_function(&mut self, name: &str, args: &[Expr], console: &Console) -> Result<Option<Value>> {
+        let func = try!(self.lookup_callable(name));
+        let mut arg_values = Vec::new();
+
+        // Evaluate the arguments
+        for arg in args.iter() {
+            arg_values.push(try!(arg.eval(self, console)));
+        }
+
+        self.call(func, arg_values, console)
+    }
+
+    // Calls a function with the values of its arguments.
+    pub fn call(&mut self, func: Arc<Function>, args: Vec<Value>, console: &Console) -> Result<Option<Value>> {
+        // Check that the correct number of arguments is given
+        match func.arity() {
+            Some(arity) if arity != args.len() => return Error::argument_error(
+                &format!("{} takes {} arguments, but {} were given",
+                    capitalize(&func.description()), arity, args.len())),
+            _ => (),
+        }
+
+        let result = match func.kind {
+            FunctionKind::Pal { ref params, ref body, ref captured } =>
+                try!(self.call_pal(&func, params, body, captured, args, console)),
+            FunctionKind::Native { ref params, func: ref native } =>
+                try!(call_native(&func, params, &**native, args)),
+            FunctionKind::Builtin => {
+                let name = func.name.clone().unwrap_or_default();
+                Some(try!(call_builtin(&name, &name, args, self, console)))
+            }
+        };
+
+        match result {
+            // Verify that the returned value matches the return type of the function
+            Some(val) => if val.is_a(&func.return_type) {
+                Ok(Some(val))
+            } else {
+                Error::type_error(
+                    &format!("{} is supposed to return {}, but instead it returns {}",
+                        capitalize(&func.description()), func.return_type.as_string_with_article(),
+                        val.type_string_with_article()))
+            },
+            // Verify that functions with a non-void return type have returned a value.
+            None => if func.return_type != Type::Void {
+                Error::type_error(
+                    &format!("{} is supposed to return {}, but instead it returns nothing",
+                        capitalize(&func.description()), func.return_type.as_string_with_article()))
+            } else {
+                Ok(None)
+            },
+        }
+    }
+
+    // Runs the body of a function written in pal, returning the value it returns, if any.
+    fn call_pal(&mut self, func: &Function, params: &[String], body: &[Statement], captured: &HashMap<String, Value>,
+                args: Vec<Value>, console: &Console) -> Result<Option<Value>> {
         macro_rules! try_or_exit_scope {
             ($e:expr, $state:expr) => { match $e {
                 Ok(t) => t,
                 e @ Err(_) => {
-                    $state.exit_scope();
+                    $state.exit_function();
                     return e;
                 }
             }}
         }
 
-        // Look up the function
-        let (return_type, params, body) = match self.functions.get(name) {
-            Some(&Function { ref return_type, ref params, ref body }) =>
-                (return_type.clone(), params.clone(), body.clone()),
-            None => return Error::undef_func_error(
-                &format!("The function `{}` has not been defined, so it can't be called", name))
-        };
-
-        // Check that the correct number of arguments is given
-        if params.len() != args.len() {
-            return Error::argument_error(
-                &format!("The function {} takes {} arguments, but {} were given", name, params.len(), args.len()));
-        }
-
-        let mut arg_values = Vec::new();
-
-        // Evaluate the arguments
-        for arg in args.iter() {
-            arg_values.push(try!(arg.eval(self, stream.clone())));
+        if let Some(max) = self.config.limits.max_call_depth {
+            // The first frame is the top level of the program rather than a function call.
+            if self.frames.len() > max {
+                return Error::resource_limit_error(
+                    &format!("Calling {} would make more than {} function calls in progress at once",
+                        func.description(), max));
+            }
         }
 
-        self.enter_scope();
+        self.enter_function(captured);
 
         // Assign the argument values to the parameters
-        for (i, val) in arg_values.into_iter().enumerate() {
-            self.define_var(&params[i], val);
+        for (param, val) in params.iter().zip(args.into_iter()) {
+            self.define_var(param, val);
         }
 
         // Evaluate the function body
-        for stmt in &body {
+        for stmt in body {
             // Check if the function has returned
-            if let Some(val) = try_or_exit_scope!(stmt.eval(self, stream.clone()), self) {
-                self.exit_scope();
-
-                // Verify that the returned value matches the return type of the function
-                return if val.is_a(&return_type) {
-                    Ok(Some(val))
-                } else {
-                    Error::type_error(
-                        &format!("The function {} is supposed to return {}, but instead it returns {}",
-                            name, return_type.as_string_with_article(), val.type_string_with_article()))
-                };
+            let result = stmt.eval(self, console)
+                .map_err(|e| e.with_note(format!("This happened inside {}", func.description())));
+
+            // `break` and `continue` can't leave a function, since they're only allowed inside loops.
+            if let Some(Flow::Return(val)) = try_or_exit_scope!(result, self) {
+                self.exit_function();
+                return Ok(Some(val));
             }
         }
 
-        self.exit_scope();
+        self.exit_function();
+        Ok(None)
+    }
 
-        // Verify that functions with a non-void return type have returned a value.
-        if return_type != Type::Void {
-            Error::type_error(
-                &format!("The function {} is supposed to return {}, but instead it returns nothing",
-                    name, return_type.as_string_with_article()))
-        } else {
-            Ok(None)
+    // Registers a Rust function so that pal programs can call it by name like any other function.
+    // Arguments are checked against `params` before the function is called, and its result is
+    // checked against `return_type`; `Type::Any` accepts any value. An error message returned by the
+    // function stops the program with a `NativeError`.
+    pub fn register_native<F>(&mut self, name: &str, params: &[Type], return_type: Type, func: F) -> PalResult<()>
+        where F: Fn(Vec<Value>) -> result::Result<Option<Value>, String> + Send + Sync + 'static {
+        if self.functions.contains_key(name) {
+            return Err(PalError::from(Error::new(ErrorType::RedefinedFunction,
+                &format!("The function {} has already been defined, so it can't be defined again", name))));
         }
+
+        let func = Function::native(name, params, &return_type, Box::new(func));
+        self.functions.insert(String::from(name), Arc::new(func));
+        Ok(())
+    }
+
+    // Copies the local variables visible in the current function so that an anonymous function
+    // created here can still use them after the function returns.
+    pub fn capture(&self) -> HashMap<String, Value> {
+        let mut captured = HashMap::new();
+
+        // Inner scopes are copied last so that their variables shadow those of outer ones.
+        for scope in self.frames.last().unwrap() {
+            for (var, val) in scope.vars() {
+                captured.insert(var.clone(), val.clone());
+            }
+        }
+
+        captured
     }
 
     // Defines a new function given its return type, name, parameters, and body, returning an error
@@ -120,37 +277,98 @@ impl State {
                 &format!("The function {} has already been defined, so it can't be defined again", name));
         }
 
-        self.functions.insert(String::from(name), Function::new(return_type, params, body));
+        let func = Function::new(Some(name), return_type, params, body, HashMap::new());
+        self.functions.insert(String::from(name), Arc::new(func));
         Ok(())
     }
 
-    // Defines a new variable in the current scope.
+    // Defines a new variable in the innermost scope, replacing any variable of the same name in it.
     pub fn define_var(&mut self, var: &str, val: Value) {
-        if let Some(ref mut scope) = self.current {
+        if let Some(scope) = self.frames.last_mut().unwrap().last_mut() {
             return scope.assign(var, val);
         }
 
         self.global.assign(var, val);
     }
 
-    fn enter_scope(&mut self) {
-        self.current = Some(Scope::new(self.current.clone()));
+    // Declares a new variable in the innermost scope, returning an error if it's a block that already
+    // has a variable of that name. Variables in outer scopes are shadowed rather than replaced. At the
+    // top level of the program, a `let` replaces any variable of the same name, so that e.g. a line
+    // can be entered again in the REPL.
+    pub fn declare_var(&mut self, var: &str, val: Value) -> Result<()> {
+        let declared = match self.frames.last().unwrap().last() {
+            Some(scope) => scope.contains_var(var),
+            None => false,
+        };
+
+        if declared {
+            return Error::redef_var_error(
+                &format!("The variable `{}` has already been declared in this block, so it can't be declared again", var));
+        }
+
+        self.define_var(var, val);
+        Ok(())
     }
 
-    fn exit_scope(&mut self) {
-        let new = match self.current {
-            Some(ref scope) => scope.parent.clone().map(|s| *s),
-            None => None,
-        };
+    // Starts a new scope for a block within the current function.
+    pub fn enter_block(&mut self) {
+        self.frames.last_mut().unwrap().push(Scope::new());
+    }
 
-        self.current = new;
+    pub fn exit_block(&mut self) {
+        self.frames.last_mut().unwrap().pop();
+    }
+
+    // Starts the scopes of a function call: one with the variables captured by the function, if any,
+    // and one for its parameters.
+    fn enter_function(&mut self, captured: &HashMap<String, Value>) {
+        let mut frame = Vec::new();
+
+        if !captured.is_empty() {
+            let mut scope = Scope::new();
+
+            for (var, val) in captured {
+                scope.assign(var, val.clone());
+            }
+
+            frame.push(scope);
+        }
+
+        frame.push(Scope::new());
+        self.frames.push(frame);
+    }
+
+    fn exit_function(&mut self) {
+        self.frames.pop();
+    }
+
+    // Looks up a function defined by name.
+    pub fn lookup_func(&self, name: &str) -> Option<Arc<Function>> {
+        self.functions.get(name).cloned()
+    }
+
+    // Finds the function to call for a name: a variable holding a function, or else a function
+    // defined with that name.
+    pub fn lookup_callable(&self, name: &str) -> Result<Arc<Function>> {
+        match self.lookup(name) {
+            Some(&Value::Function(ref func)) => return Ok(func.clone()),
+            Some(val) => return Error::type_error(
+                &format!("`{}` is {}, so it can't be called", name, val.type_string_with_article())),
+            None => (),
+        }
+
+        match self.lookup_func(name) {
+            Some(func) => Ok(func),
+            None => Error::undef_func_error(
+                &format!("The function `{}` has not been defined, so it can't be called", name)),
+        }
     }
 
     // Looks up the value of a variable.
     pub fn lookup(&self, var: &str) -> Option<&Value> {
-        // Check the current scope
-        if let Some(ref scope) = self.current {
-            if let Some(ref val) = scope.lookup(var) {
+        // Check the scopes of the current function, innermost first
+        for scope in self.frames.last().unwrap().iter().rev() {
+            if let Some(val) = scope.lookup(var) {
                 return Some(val);
             }
         }
@@ -163,23 +381,31 @@ impl State {
     // evaluation test cases).
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
-        let mut len = self.global.len();
+        self.frames.last().unwrap().iter().fold(self.global.len(), |len, scope| len + scope.len())
+    }
+}
 
-        // Check the current scope
-        let mut temp = match self.current {
-            Some(ref s) => {
-                len += s.len();
-                s.parent()
-            }
-            None => return len,
-        };
+// Capitalizes the first letter of a string, for descriptions at the start of a sentence.
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
 
-        // Work through each parent scope until none is found.
-        while let Some(scope) = temp {
-            len += scope.len();
-            temp = scope.parent();
-        }
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
 
-        len
+// Calls a function written in Rust after checking the types of its arguments.
+fn call_native(func: &Function, params: &[Type], native: &NativeFn, args: Vec<Value>) -> Result<Option<Value>> {
+    for (i, (param, arg)) in params.iter().zip(args.iter()).enumerate() {
+        if !arg.is_a(param) {
+            return Error::type_error(
+                &format!("Argument {} of {} has to be {}, but it's {}", i + 1, func.description(),
+                    param.as_string_with_article(), arg.type_string_with_article()));
+        }
     }
+
+    native(args).map_err(|message| {
+        Error::new(ErrorType::Native, &message).with_note(format!("This happened inside {}", func.description()))
+    })
 }
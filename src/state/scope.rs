@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::hash_map::{self, HashMap};
 
 use ast::Value;
 
@@ -6,13 +6,11 @@ use ast::Value;
 pub struct Scope {
     // Maps variable names to values.
     map: HashMap<String, Value>,
-    // The scope containing this one.
-    pub parent: Option<Box<Scope>>,
 }
 
 impl Scope {
-    pub fn new(parent: Option<Self>) -> Self {
-        Scope { map: HashMap::new(), parent: parent.map(Box::new) }
+    pub fn new() -> Self {
+        Scope { map: HashMap::new() }
     }
 
     // Assign a value to a given variable.
@@ -30,13 +28,13 @@ impl Scope {
         self.map.contains_key(var)
     }
 
+    // Returns an iterator over the variables in the scope and their values.
+    pub fn vars(&self) -> hash_map::Iter<String, Value> {
+        self.map.iter()
+    }
+
     // Returns number of variables in the scope.
     pub fn len(&self) -> usize {
         self.map.len()
     }
-
-    // Returns a reference to the containing scope.
-    pub fn parent(&self) -> Option<&Box<Scope>> {
-        self.parent.as_ref()
-    }
 }
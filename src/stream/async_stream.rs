@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use futures::{self, Poll};
+
+use stream::{Event, Stream};
+
+// An asynchronous interface to the events of a program running with a `Stream`: a `futures::Stream`
+// of its events that ends after the `Finished` event. Polling it never blocks the thread doing the
+// polling, so one thread can wait on the events of many programs.
+//
+// Only waiting for events is asynchronous. The interpreter can't suspend a program part of the way
+// through, so each program still runs on an OS thread of its own, and that thread is parked while
+// the program waits for input. A host running many sessions needs a thread for each of them.
+//
+// It isn't `Clone`, since the stream only remembers the last task that polled it, so only one task
+// can be woken when an event is sent.
+pub struct AsyncStream {
+    stream: Arc<Stream>,
+}
+
+impl AsyncStream {
+    pub fn new(stream: Arc<Stream>) -> Self {
+        AsyncStream { stream: stream }
+    }
+
+    // Gives the program a line of input. The line is only added to the stream's input queue, so this
+    // never blocks and can be called from a task.
+    pub fn send_input(&self, s: &str) {
+        self.stream.write_input(s);
+    }
+
+    // Asks the program to stop; see `ProgramHandle::cancel`.
+    pub fn cancel(&self) {
+        self.stream.cancel();
+    }
+
+    #[inline]
+    pub fn stream(&self) -> &Arc<Stream> {
+        &self.stream
+    }
+}
+
+impl futures::Stream for AsyncStream {
+    type Item = Event;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Event>, ()> {
+        Ok(self.stream.poll_event())
+    }
+}
@@ -0,0 +1,291 @@
+#[cfg(test)]
+mod test;
+
+mod async_stream;
+
+pub use self::async_stream::AsyncStream;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ast::Span;
+use console::Console;
+use error::PalError;
+
+use futures::Async;
+use futures::task::{self, Task};
+
+#[derive(Debug)]
+pub enum Event {
+    // The program was cancelled with `ProgramHandle::cancel`. It's followed by `Finished`.
+    Cancelled,
+    // The program had a syntax error or failed while running. `kind` is the name of the error, e.g.
+    // "TypeError", and `span` is the part of the program that caused it. A program with several
+    // syntax errors sends an event for each of them.
+    Error { kind: String, message: String, span: Option<Span>, notes: Vec<String> },
+    Finished,
+    NeedsInput,
+    Output(String),
+}
+
+// Returned by `Stream::get_event_timeout` when no event was sent in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout;
+
+#[derive(Default)]
+struct StreamState {
+    // Lines of input that have been written but not read yet.
+    input: VecDeque<String>,
+    // Whether `close_input` has been called, so no more input will be written.
+    input_closed: bool,
+    // Whether the program is waiting in `read_input`.
+    waiting_for_input: bool,
+    events: VecDeque<Event>,
+    // Whether the `Finished` event has been sent, so no more events will follow it.
+    finished: bool,
+    // The task waiting for an event in `poll_event`, if any.
+    event_task: Option<Task>,
+}
+
+// Connects a program running on another thread to the code that started it. Input and events are
+// queued, so nothing is lost if one side gets ahead of the other, and each side waits on its own
+// condition variable until there's something for it in the queue.
+pub struct Stream {
+    state: Mutex<StreamState>,
+    // Signalled when input is written or the program is cancelled.
+    input_ready: Condvar,
+    // Signalled when an event is sent.
+    event_ready: Condvar,
+    // Set when the program should stop. It's checked between statements, so it's kept outside of the
+    // mutex to make checking it cheap.
+    cancelled: AtomicBool,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream {
+            state: Mutex::new(StreamState::default()),
+            input_ready: Condvar::new(),
+            event_ready: Condvar::new(),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    // Asks the program to stop, waking it up if it's waiting for input.
+    pub fn cancel(&self) {
+        // The flag is set while holding the lock so that `read_input` can't check it just before it's
+        // set and then wait without seeing the notification.
+        let _state = self.state.lock().unwrap();
+
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.input_ready.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn cancelled(&self) {
+        self.send(Event::Cancelled);
+    }
+
+    pub fn finished(&self) {
+        self.send(Event::Finished);
+    }
+
+    // Waits for a line of input, returning an empty string if the program is cancelled first or the
+    // input has been closed, as at the end of a file. `NeedsInput` is only sent if no input has been
+    // written ahead of time.
+    pub fn read_input(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+
+        if state.input.is_empty() && !state.input_closed && !self.is_cancelled() {
+            state.events.push_back(Event::NeedsInput);
+            self.event_ready.notify_all();
+        }
+
+        let line = loop {
+            if let Some(line) = state.input.pop_front() {
+                break line;
+            }
+
+            if state.input_closed || self.is_cancelled() {
+                break String::new();
+            }
+
+            state.waiting_for_input = true;
+            state = self.input_ready.wait(state).unwrap();
+        };
+
+        state.waiting_for_input = false;
+        line
+    }
+
+    // Whether the program is waiting for a line of input that hasn't been written yet.
+    pub fn is_waiting_for_input(&self) -> bool {
+        self.state.lock().unwrap().waiting_for_input
+    }
+
+    // Gives the program a line of input. Lines are read in the order they're written, whether or
+    // not the program is waiting for input yet.
+    pub fn write_input(&self, s: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.input.push_back(String::from(s));
+        self.input_ready.notify_one();
+    }
+
+    // Signals that there will be no more input, e.g. because the client giving it has disconnected.
+    // Input that has already been written can still be read.
+    pub fn close_input(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        state.input_closed = true;
+        self.input_ready.notify_all();
+    }
+
+    pub fn write_output(&self, s: &str) {
+        self.send(Event::Output(String::from(s)));
+    }
+
+    fn send(&self, event: Event) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Event::Finished = event {
+            state.finished = true;
+        }
+
+        state.events.push_back(event);
+        self.event_ready.notify_all();
+
+        if let Some(task) = state.event_task.take() {
+            task.notify();
+        }
+    }
+
+    // Waits for the next event. Returns `None` once the `Finished` event has been received, since no
+    // more events will be sent.
+    pub fn get_event(&self) -> Option<Event> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+
+            if state.finished {
+                return None;
+            }
+
+            state = self.event_ready.wait(state).unwrap();
+        }
+    }
+
+    // Returns the next event if there is one, like `get_event`, but rather than waiting for one it
+    // arranges for the current task to be woken up when one is sent. It must be called from within a
+    // task, e.g. when polling a future. Only the task that polled last is woken, so events should only
+    // be polled from one task.
+    pub fn poll_event(&self) -> Async<Option<Event>> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(event) = state.events.pop_front() {
+            return Async::Ready(Some(event));
+        }
+
+        if state.finished {
+            return Async::Ready(None);
+        }
+
+        state.event_task = Some(task::current());
+        Async::NotReady
+    }
+
+    // Returns the next event if there is one already, without waiting.
+    pub fn try_get_event(&self) -> Option<Event> {
+        self.state.lock().unwrap().events.pop_front()
+    }
+
+    // Waits for the next event for at most `timeout`. Like `get_event`, it returns `Ok(None)` once the
+    // program has finished, and it fails with `Timeout` if there's no event by then.
+    pub fn get_event_timeout(&self, timeout: Duration) -> Result<Option<Event>, Timeout> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if state.finished {
+                return Ok(None);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(Timeout);
+            }
+
+            state = self.event_ready.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+// Lets a program running on another thread do its I/O through the stream.
+impl Console for Stream {
+    fn write_output(&self, s: &str) {
+        Stream::write_output(self, s)
+    }
+
+    fn read_line(&self) -> String {
+        self.read_input()
+    }
+
+    fn report_error(&self, e: &PalError, _file: &str, _text: &str) {
+        let errors: Vec<&PalError> = match *e {
+            PalError::Multiple(ref errors) => errors.iter().collect(),
+            ref e => vec![e],
+        };
+
+        for e in errors {
+            self.send(Event::Error { kind: e.kind_name(), message: e.message(), span: e.span(), notes: e.notes() });
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        Stream::is_cancelled(self)
+    }
+}
+
+// Controls a program started by `run_program_with_stream` on another thread.
+pub struct ProgramHandle {
+    stream: Arc<Stream>,
+    thread: JoinHandle<()>,
+}
+
+impl ProgramHandle {
+    pub fn new(stream: Arc<Stream>, thread: JoinHandle<()>) -> Self {
+        ProgramHandle { stream: stream, thread: thread }
+    }
+
+    // Asks the program to stop. It stops before its next statement, or straight away if it's waiting
+    // for input, and then sends `Event::Cancelled` followed by `Event::Finished`. Cancelling a
+    // program that has already finished does nothing.
+    pub fn cancel(&self) {
+        self.stream.cancel();
+    }
+
+    // Waits for the thread running the program to finish.
+    pub fn join(self) -> thread::Result<()> {
+        self.thread.join()
+    }
+
+    // Cancels the program and waits for it to stop.
+    pub fn cancel_and_join(self) -> thread::Result<()> {
+        self.cancel();
+        self.join()
+    }
+}
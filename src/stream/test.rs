@@ -0,0 +1,272 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream as FuturesStream};
+use futures::future;
+
+use ast::Span;
+use config::{Config, Limits};
+use {run_program_async, run_program_async_with_config, run_program_with_stream};
+use stream::{Event, Stream, Timeout};
+
+// Collects the events of a program until it finishes.
+fn events_until_finished(stream: &Arc<Stream>) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    while let Some(event) = stream.get_event() {
+        match event {
+            Event::Finished => break,
+            event => events.push(event),
+        }
+    }
+
+    events
+}
+
+// Joins the output events of a program into one string.
+fn output(events: &[Event]) -> String {
+    events.iter().fold(String::new(), |mut out, event| {
+        match *event {
+            Event::Output(ref s) => out.push_str(s),
+            ref event => panic!("expected only output, got {:?}", event),
+        }
+
+        out
+    })
+}
+
+#[test]
+fn cancel_infinite_loop() {
+    let (stream, handle) = run_program_with_stream("let i = 0;\nwhile (true) { i = i + 1; }");
+
+    handle.cancel_and_join().unwrap();
+
+    let events = events_until_finished(&stream);
+    assert_eq!(1, events.len());
+    assert!(match events[0] { Event::Cancelled => true, _ => false });
+}
+
+#[test]
+fn cancel_while_waiting_for_input() {
+    let (stream, handle) = run_program_with_stream("let x = to_int(read_line());\nprint x;");
+
+    loop {
+        match stream.get_event() {
+            Some(Event::NeedsInput) => break,
+            Some(event) => panic!("expected the program to wait for input, got {:?}", event),
+            None => (),
+        }
+    }
+
+    handle.cancel_and_join().unwrap();
+
+    // `read_line` fails when the program is cancelled, so `to_int` is never given the empty string.
+    let events = events_until_finished(&stream);
+    assert_eq!(1, events.len());
+    assert!(match events[0] { Event::Cancelled => true, _ => false });
+}
+
+#[test]
+fn cancel_while_printing_input() {
+    let (stream, handle) = run_program_with_stream("print read_line();");
+
+    loop {
+        match stream.get_event() {
+            Some(Event::NeedsInput) => break,
+            Some(event) => panic!("expected the program to wait for input, got {:?}", event),
+            None => (),
+        }
+    }
+
+    handle.cancel_and_join().unwrap();
+
+    // Nothing is printed after the program is cancelled.
+    let events = events_until_finished(&stream);
+    assert_eq!(1, events.len());
+    assert!(match events[0] { Event::Cancelled => true, _ => false });
+}
+
+#[test]
+fn cancel_after_finishing() {
+    let (stream, handle) = run_program_with_stream("print 1;");
+
+    handle.join().unwrap();
+    stream.cancel();
+
+    let events = events_until_finished(&stream);
+    assert_eq!(1, events.len());
+    assert!(match events[0] { Event::Output(ref s) => s == "1", _ => false });
+}
+
+#[test]
+fn runtime_error_event() {
+    let (stream, handle) = run_program_with_stream("print 1;\nlet x = 1 + true;");
+    handle.join().unwrap();
+
+    let events = events_until_finished(&stream);
+    assert_eq!(2, events.len());
+    assert!(match events[0] { Event::Output(ref s) => s == "1", _ => false });
+
+    match events[1] {
+        Event::Error { ref kind, ref span, .. } => {
+            assert_eq!("TypeError", *kind);
+            assert_eq!(Some(Span::new(17, 25)), *span);
+        }
+        ref event => panic!("expected an error, got {:?}", event),
+    }
+}
+
+#[test]
+fn syntax_error_events() {
+    let (stream, handle) = run_program_with_stream("let x = ;\nlet y = 1 2;\nprint 1;");
+    handle.join().unwrap();
+
+    let events = events_until_finished(&stream);
+    assert_eq!(2, events.len());
+
+    for event in events {
+        match event {
+            Event::Error { ref kind, ref span, .. } => {
+                assert_eq!("SyntaxError", *kind);
+                assert!(span.is_some());
+            }
+            event => panic!("expected a syntax error, got {:?}", event),
+        }
+    }
+}
+
+#[test]
+fn no_events_after_finished() {
+    let (stream, handle) = run_program_with_stream("print 1;");
+    handle.join().unwrap();
+
+    assert_eq!("1", output(&events_until_finished(&stream)));
+    assert!(stream.get_event().is_none());
+    assert!(stream.try_get_event().is_none());
+    assert!(match stream.get_event_timeout(Duration::from_secs(10)) { Ok(None) => true, _ => false });
+}
+
+#[test]
+fn get_event_timeout() {
+    let (stream, handle) = run_program_with_stream("let x = read_line();");
+
+    // The program asks for input and then waits, so no other event arrives before the timeout.
+    assert!(match stream.get_event_timeout(Duration::from_secs(10)) { Ok(Some(Event::NeedsInput)) => true, _ => false });
+
+    let start = Instant::now();
+    assert!(match stream.get_event_timeout(Duration::from_millis(50)) { Err(Timeout) => true, _ => false });
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(stream.try_get_event().is_none());
+
+    stream.write_input("done");
+    handle.join().unwrap();
+
+    assert!(match stream.try_get_event() { Some(Event::Finished) => true, _ => false });
+}
+
+#[test]
+fn stress_output() {
+    let (stream, _) = run_program_with_stream("for i in range(1, 2000) { print_line i; }");
+
+    let expected: String = (1..2001).map(|i| format!("{}\n", i)).collect();
+    assert_eq!(expected, output(&events_until_finished(&stream)));
+}
+
+#[test]
+fn stress_input_written_ahead() {
+    let program = "let total = 0;\nfor i in range(1, 500) { total = total + to_int(read_line()); }\nprint total;";
+    let (stream, _) = run_program_with_stream(program);
+
+    // Write all of the input straight away, racing the program as it starts reading it.
+    let writer = {
+        let stream = stream.clone();
+        thread::spawn(move || for i in 1..501 {
+            stream.write_input(&format!("{}", i));
+        })
+    };
+
+    let mut out = String::new();
+
+    while let Some(event) = stream.get_event() {
+        match event {
+            Event::Finished => break,
+            Event::NeedsInput => (),
+            Event::Output(s) => out.push_str(&s),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    writer.join().unwrap();
+    assert_eq!("125250", out);
+}
+
+#[test]
+fn stress_input_on_request() {
+    let program = "for i in range(1, 500) { print read_line(); }";
+    let (stream, _) = run_program_with_stream(program);
+    let mut sent = 0;
+    let mut out = String::new();
+
+    while let Some(event) = stream.get_event() {
+        match event {
+            Event::Finished => break,
+            Event::NeedsInput => {
+                sent += 1;
+                stream.write_input(&format!("{},", sent));
+            }
+            Event::Output(s) => out.push_str(&s),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    let expected: String = (1..501).map(|i| format!("{},", i)).collect();
+    assert_eq!(500, sent);
+    assert_eq!(expected, out);
+}
+
+#[test]
+fn async_events() {
+    let (events, handle) = run_program_async("let name = read_line();\nprint \"hi ${name}\";");
+    let mut iter = events.wait();
+
+    assert!(match iter.next() { Some(Ok(Event::NeedsInput)) => true, _ => false });
+    iter.get_ref().send_input("pal");
+
+    let rest: Vec<_> = iter.map(|e| e.unwrap()).collect();
+    handle.join().unwrap();
+
+    assert_eq!(2, rest.len());
+    assert_eq!("hi pal", output(&rest[..1]));
+    assert!(match rest[1] { Event::Finished => true, _ => false });
+}
+
+#[test]
+fn async_limits() {
+    let config = Config { limits: Limits { max_statements: Some(100), ..Limits::default() }, ..Config::default() };
+    let (events, handle) = run_program_async_with_config("while (true) { }", config);
+
+    let events: Vec<_> = events.wait().map(|e| e.unwrap()).collect();
+    handle.join().unwrap();
+
+    assert_eq!(2, events.len());
+    assert!(match events[0] { Event::Error { ref kind, .. } => kind == "ResourceLimitError", _ => false });
+    assert!(match events[1] { Event::Finished => true, _ => false });
+}
+
+#[test]
+fn many_async_sessions() {
+    // The events of every program are polled from this thread, which only waits when none of them has
+    // an event. Each program still runs on a thread of its own.
+    let sessions: Vec<_> = (0..20)
+        .map(|i| run_program_async(&format!("for i in range(1, 50) {{ print {}; }}", i)).0.collect())
+        .collect();
+
+    let results = future::join_all(sessions).wait().unwrap();
+
+    for (i, mut events) in results.into_iter().enumerate() {
+        assert!(match events.pop() { Some(Event::Finished) => true, _ => false });
+        let expected: String = (0..50).map(|_| format!("{}", i)).collect();
+        assert_eq!(expected, output(&events));
+    }
+}
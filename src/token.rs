@@ -1,5 +1,6 @@
 // Large portions of this are borrowed/copied from [https://github.com/nikomatsakis/lalrpop/blob/d17fdd812731e2794e0196bb21f669534f1e963e/lalrpop/src/tok/mod.rs]
 
+use std::fmt;
 use std::str::CharIndices;
 use unicode_xid::UnicodeXID;
 
@@ -22,17 +23,31 @@ pub enum Token<'input> {
     True,
     False,
     I64(&'input str),
+    F64(&'input str),
     StringLiteral(&'input str),
 
+    // The pieces of an interpolated string like `"a ${b} c ${d} e"`: `"a ${`, `} c ${` and `} e"`.
+    // Like `StringLiteral`, the text doesn't include the delimiters or decode escapes.
+    StringStart(&'input str),
+    StringMiddle(&'input str),
+    StringEnd(&'input str),
+
     // Identifiers
     Ident(&'input str),
 
+    // Comments (only produced by a tokenizer that keeps trivia); the text includes the delimiters
+    LineComment(&'input str),
+    BlockComment(&'input str),
+
     // Keywords
+    Break,
+    Continue,
     Delete,
     Else,
     For,
     If,
     In,
+    Keys,
     Let,
     Letters,
     Length,
@@ -42,11 +57,17 @@ pub enum Token<'input> {
     ReadLine,
     Return,
     Step,
+    ToFloat,
+    ToInt,
+    Values,
     While,
 
     // Types
     Array,
     Boolean,
+    Dictionary,
+    Float,
+    Function,
     Int,
     Str,
     Void,
@@ -65,6 +86,7 @@ pub enum Token<'input> {
     Star,
 
     // Punctuation
+    Colon,
     Comma,
     Semicolon,
     LeftParen,
@@ -75,12 +97,92 @@ pub enum Token<'input> {
     RightBracket,
 }
 
+impl <'input> fmt::Display for Token<'input> {
+    fn fmt(&self, mut fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            True => write!(fmt, "true"),
+            False => write!(fmt, "false"),
+            I64(s) => write!(fmt, "{}", s),
+            F64(s) => write!(fmt, "{}", s),
+            StringLiteral(s) => write!(fmt, "\"{}\"", s),
+            StringStart(s) => write!(fmt, "\"{}${{", s),
+            StringMiddle(s) => write!(fmt, "}}{}${{", s),
+            StringEnd(s) => write!(fmt, "}}{}\"", s),
+            Ident(s) => write!(fmt, "{}", s),
+            LineComment(s) => write!(fmt, "{}", s),
+            BlockComment(s) => write!(fmt, "{}", s),
+            Break => write!(fmt, "break"),
+            Continue => write!(fmt, "continue"),
+            Delete => write!(fmt, "delete"),
+            Else => write!(fmt, "else"),
+            For => write!(fmt, "for"),
+            If => write!(fmt, "if"),
+            In => write!(fmt, "in"),
+            Keys => write!(fmt, "keys"),
+            Let => write!(fmt, "let"),
+            Letters => write!(fmt, "letters"),
+            Length => write!(fmt, "length"),
+            Print => write!(fmt, "print"),
+            PrintLine => write!(fmt, "print_line"),
+            Range => write!(fmt, "range"),
+            ReadLine => write!(fmt, "read_line"),
+            Return => write!(fmt, "return"),
+            Step => write!(fmt, "step"),
+            ToFloat => write!(fmt, "to_float"),
+            ToInt => write!(fmt, "to_int"),
+            Values => write!(fmt, "values"),
+            While => write!(fmt, "while"),
+            Array => write!(fmt, "array"),
+            Boolean => write!(fmt, "boolean"),
+            Dictionary => write!(fmt, "dictionary"),
+            Float => write!(fmt, "float"),
+            Function => write!(fmt, "function"),
+            Int => write!(fmt, "int"),
+            Str => write!(fmt, "string"),
+            Void => write!(fmt, "void"),
+            Bang => write!(fmt, "!"),
+            DoubleAmp => write!(fmt, "&&"),
+            DoubleBars => write!(fmt, "||"),
+            Equal => write!(fmt, "="),
+            GreaterThan => write!(fmt, ">"),
+            LessThan => write!(fmt, "<"),
+            Minus => write!(fmt, "-"),
+            Percent => write!(fmt, "%"),
+            Plus => write!(fmt, "+"),
+            Slash => write!(fmt, "/"),
+            Star => write!(fmt, "*"),
+            Comma => write!(fmt, ","),
+            Colon => write!(fmt, ":"),
+            Semicolon => write!(fmt, ";"),
+            LeftParen => write!(fmt, "("),
+            RightParen => write!(fmt, ")"),
+            LeftBrace => write!(fmt, "{{"),
+            RightBrace => write!(fmt, "}}"),
+            LeftBracket => write!(fmt, "["),
+            RightBracket => write!(fmt, "]"),
+        }
+    }
+}
+
+// The words that `Tokenizer` reads as keywords rather than identifiers, e.g. for an editor to offer
+// as completions. This has to be kept in sync with `identifierish`.
+pub const KEYWORDS: &'static [&'static str] = &[
+    "array", "boolean", "break", "continue", "delete", "dictionary", "else", "false", "float", "for", "function",
+    "if", "in", "int", "keys", "let", "letters", "length", "print", "print_line", "range", "read_line", "return",
+    "step", "string", "to_float", "to_int", "true", "values", "void", "while",
+];
+
 pub struct Tokenizer<'input> {
     text: &'input str,
     chars: CharIndices<'input>,
     look_ahead: Option<(usize, char)>,
     line_num: usize,
     shift: usize,
+    // Whether comments should be returned as tokens rather than skipped.
+    keep_trivia: bool,
+    // The number of unclosed braces inside each string interpolation that is currently open, so
+    // that the `}` ending the interpolation can be told apart from one ending a block.
+    interpolations: Vec<usize>,
 }
 
 macro_rules! eof {
@@ -102,12 +204,22 @@ impl <'input> Tokenizer<'input> {
             look_ahead: None,
             line_num: 1,
             shift: shift,
+            keep_trivia: false,
+            interpolations: Vec::new(),
         };
 
         t.bump();
         t
     }
 
+    // Creates a tokenizer that returns comments as tokens instead of skipping them, so that tools
+    // like a formatter can preserve them.
+    pub fn with_trivia(text: &'input str, shift: usize) -> Self {
+        let mut t = Self::new(text, shift);
+        t.keep_trivia = true;
+        t
+    }
+
     fn next_unshifted(&mut self) -> Option<Result<Spanned<Token<'input>>, Error>> {
         loop {
             return match self.look_ahead {
@@ -150,7 +262,7 @@ impl <'input> Tokenizer<'input> {
                 }
                 Some((idx0, '-')) => {
                     match self.bump() {
-                        Some((_, c)) if c.is_digit(10) => Some(Ok(self.num(idx0))),
+                        Some((_, c)) if c.is_digit(10) => Some(self.num(idx0)),
                         _ => Some(Ok((idx0, Minus, idx0 + 1))),
                     }
                 }
@@ -163,8 +275,20 @@ impl <'input> Tokenizer<'input> {
                     Some(Ok((idx0, Plus, idx0 + 1)))
                 }
                 Some((idx0, '/')) => {
-                    self.bump();
-                    Some(Ok((idx0, Slash, idx0 + 1)))
+                    let comment = match self.bump() {
+                        Some((_, '/')) => self.line_comment(idx0),
+                        Some((_, '*')) => match self.block_comment(idx0) {
+                            Ok(comment) => comment,
+                            Err(e) => return Some(Err(e)),
+                        },
+                        _ => return Some(Ok((idx0, Slash, idx0 + 1))),
+                    };
+
+                    if self.keep_trivia {
+                        Some(Ok(comment))
+                    } else {
+                        continue;
+                    }
                 }
                 Some((idx0, '*')) => {
                     self.bump();
@@ -172,6 +296,10 @@ impl <'input> Tokenizer<'input> {
                 }
 
                 // Punctuation
+                Some((idx0, ':')) => {
+                    self.bump();
+                    Some(Ok((idx0, Colon, idx0 + 1)))
+                }
                 Some((idx0, ',')) => {
                     self.bump();
                     Some(Ok((idx0, Comma, idx0 + 1)))
@@ -190,15 +318,31 @@ impl <'input> Tokenizer<'input> {
                 }
                 Some((idx0, '{')) => {
                     self.bump();
+
+                    if let Some(depth) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+
                     Some(Ok((idx0, LeftBrace, idx0 + 1)))
                 }
                 Some((idx0, '}')) => {
                     self.bump();
+
+                    // The end of an interpolation, so go back to reading the string.
+                    if self.interpolations.last() == Some(&0) {
+                        self.interpolations.pop();
+                        return Some(self.string_literal(idx0, true));
+                    }
+
+                    if let Some(depth) = self.interpolations.last_mut() {
+                        *depth -= 1;
+                    }
+
                     Some(Ok((idx0, RightBrace, idx0 + 1)))
                 }
                 Some((idx0, '"')) => {
                     self.bump();
-                    Some(self.string_literal(idx0))
+                    Some(self.string_literal(idx0, false))
                 }
                 Some((idx0, '[')) => {
                     self.bump();
@@ -210,7 +354,7 @@ impl <'input> Tokenizer<'input> {
                 }
 
                 // Number
-                Some((idx0, c)) if c.is_digit(10) => Some(Ok(self.num(idx0))),
+                Some((idx0, c)) if c.is_digit(10) => Some(self.num(idx0)),
 
                 // Words
                 Some((idx0, c)) if is_identifier_start(c) => Some(Ok(self.identifierish(idx0))),
@@ -238,27 +382,123 @@ impl <'input> Tokenizer<'input> {
         self.look_ahead
     }
 
-    fn string_literal(&mut self, idx0: usize) -> Result<Spanned<Token<'input>>, Error> {
-        let mut escape = false;
-        let terminate = |c: char| {
-            if escape {
-                escape = false;
-                false
-            } else if c == '\\' {
-                escape = true;
-                false
-            } else {
-                c == '"'
+    // Reads a string literal up to its closing quote or the start of an interpolation. `idx0` is
+    // the index of the opening quote, or of the closing brace if `resumed` after an interpolation.
+    fn string_literal(&mut self, idx0: usize, resumed: bool) -> Result<Spanned<Token<'input>>, Error> {
+        loop {
+            match self.look_ahead {
+                Some((idx1, '"')) => {
+                    self.bump(); // consume the '"'
+                    let text = &self.text[idx0+1..idx1]; // do not include the delimiters in the str
+                    let token = if resumed { StringEnd(text) } else { StringLiteral(text) };
+
+                    return Ok((idx0, token, idx1+1));
+                }
+                Some((idx1, '$')) => {
+                    // A `$` that doesn't start an interpolation is just part of the string.
+                    if let Some((_, '{')) = self.bump() {
+                        self.bump(); // consume the '{'
+                        self.interpolations.push(0);
+
+                        let text = &self.text[idx0+1..idx1];
+                        let token = if resumed { StringMiddle(text) } else { StringStart(text) };
+
+                        return Ok((idx0, token, idx1+2));
+                    }
+                }
+                Some((idx1, '\\')) => try!(self.escape(idx1)),
+                Some((_, '\n')) => {
+                    self.bump();
+                    self.line_num += 1;
+                }
+                Some(_) => {
+                    self.bump();
+                }
+                None => return error(String::from("Unterminated string literal"), idx0),
             }
-        };
-        match self.take_until(terminate) {
-            Some(idx1) => {
-                self.bump(); // consume the '"'
-                let text = &self.text[idx0+1..idx1]; // do not include the "" in the str
-                Ok((idx0, StringLiteral(text), idx1+1))
+        }
+    }
+
+    // Checks an escape sequence in a string literal; the sequences are decoded by `unescape`.
+    fn escape(&mut self, idx0: usize) -> Result<(), Error> {
+        match self.bump() {
+            Some((_, 'n')) | Some((_, 't')) | Some((_, 'r')) |
+            Some((_, '\\')) | Some((_, '"')) | Some((_, '$')) => {
+                self.bump();
+                Ok(())
+            }
+            Some((_, 'u')) => {
+                let start = match self.bump() {
+                    Some((idx1, '{')) => idx1 + 1,
+                    _ => return error(String::from("Expected `{` after `\\u`"), idx0),
+                };
+
+                self.bump(); // consume the '{'
+
+                let end = match self.take_while(|c| c.is_digit(16)) {
+                    Some(end) => end,
+                    None => return error(String::from("Unterminated unicode escape"), idx0),
+                };
+
+                if let Some((_, '}')) = self.look_ahead {
+                    self.bump();
+                } else {
+                    return error(String::from("Unterminated unicode escape"), idx0);
+                }
+
+                let digits = &self.text[start..end];
+                let valid = digits.len() <= 6 && u32::from_str_radix(digits, 16).ok().and_then(char::from_u32).is_some();
+
+                if valid {
+                    Ok(())
+                } else {
+                    error(format!("Invalid unicode escape: \\u{{{}}}", digits), idx0)
+                }
             }
-            None => {
-                error(String::from("Unterminated string literal"), idx0)
+            Some((_, c)) => error(format!("Invalid escape sequence: \\{}", c), idx0),
+            None => error(String::from("Unterminated string literal"), idx0),
+        }
+    }
+
+    fn line_comment(&mut self, idx0: usize) -> Spanned<Token<'input>> {
+        // Leave the newline to be consumed as whitespace so that the line number is updated.
+        match self.take_until(|c| c == '\n') {
+            Some(end) => (idx0, LineComment(&self.text[idx0..end]), end),
+            None => (idx0, LineComment(&self.text[idx0..]), self.text.len()),
+        }
+    }
+
+    // Block comments can be nested, so `/* /* */ */` is a single comment.
+    fn block_comment(&mut self, idx0: usize) -> Result<Spanned<Token<'input>>, Error> {
+        let mut depth = 1;
+        self.bump(); // consume the '*'
+
+        loop {
+            match self.look_ahead {
+                Some((_, '/')) => {
+                    if let Some((_, '*')) = self.bump() {
+                        self.bump();
+                        depth += 1;
+                    }
+                }
+                Some((idx1, '*')) => {
+                    if let Some((_, '/')) = self.bump() {
+                        self.bump();
+                        depth -= 1;
+
+                        if depth == 0 {
+                            return Ok((idx0, BlockComment(&self.text[idx0..idx1 + 2]), idx1 + 2));
+                        }
+                    }
+                }
+                Some((_, '\n')) => {
+                    self.bump();
+                    self.line_num += 1;
+                }
+                Some(_) => {
+                    self.bump();
+                }
+                None => return error(String::from("Unterminated block comment"), idx0),
             }
         }
     }
@@ -267,11 +507,14 @@ impl <'input> Tokenizer<'input> {
         let (start, word, end) = self.word(idx0);
 
         match word {
+            "break" => (start, Break, end),
+            "continue" => (start, Continue, end),
             "delete" => (start, Delete, end),
             "else" => (start, Else, end),
             "for" => (start, For, end),
             "if" => (start, If, end),
             "in" => (start, In, end),
+            "keys" => (start, Keys, end),
             "let" => (start, Let, end),
             "letters" => (start, Letters, end),
             "length" => (start, Length, end),
@@ -281,9 +524,15 @@ impl <'input> Tokenizer<'input> {
             "read_line" => (start, ReadLine, end),
             "return" => (start, Return, end),
             "step" => (start, Step, end),
+            "to_float" => (start, ToFloat, end),
+            "to_int" => (start, ToInt, end),
+            "values" => (start, Values, end),
             "while" => (start, While, end),
             "array" => (start, Array, end),
             "boolean" => (start, Boolean, end),
+            "dictionary" => (start, Dictionary, end),
+            "float" => (start, Float, end),
+            "function" => (start, Function, end),
             "int" => (start, Int, end),
             "string" => (start, Str, end),
             "void" => (start, Void, end),
@@ -293,10 +542,64 @@ impl <'input> Tokenizer<'input> {
         }
     }
 
-    fn num(&mut self, idx0: usize) -> Spanned<Token<'input>> {
+    // Reads an int like `12`, or a float like `1.5` or `1e-3`.
+    fn num(&mut self, idx0: usize) -> Result<Spanned<Token<'input>>, Error> {
+        let mut end = self.digits();
+        let mut float = false;
+
+        // Only treat the '.' as a decimal point if a digit follows it.
+        if let Some((_, '.')) = self.look_ahead {
+            if self.text[end+1..].starts_with(|c: char| c.is_digit(10)) {
+                self.bump(); // consume the '.'
+                end = self.digits();
+                float = true;
+            }
+        }
+
+        // Likewise, the exponent needs at least one digit after the 'e' and optional sign.
+        let has_exponent = match self.look_ahead {
+            Some((_, 'e')) | Some((_, 'E')) => {
+                let mut rest = self.text[end+1..].chars();
+
+                match rest.next() {
+                    Some('+') | Some('-') => rest.next().map_or(false, |c| c.is_digit(10)),
+                    Some(c) => c.is_digit(10),
+                    None => false,
+                }
+            }
+            _ => false,
+        };
+
+        if has_exponent {
+            // Consume the 'e' and the sign, if there is one.
+            match self.bump() {
+                Some((_, '+')) | Some((_, '-')) => {
+                    self.bump();
+                }
+                _ => (),
+            }
+
+            end = self.digits();
+            float = true;
+        }
+
+        let text = &self.text[idx0..end];
+
+        if float {
+            Ok((idx0, F64(text), end))
+        } else if text.parse::<i64>().is_err() {
+            // The parser expects every int literal to fit in an int.
+            error(format!("`{}` is too big to be an int", text), idx0)
+        } else {
+            Ok((idx0, I64(text), end))
+        }
+    }
+
+    // Reads a run of digits, returning the index just past the last one.
+    fn digits(&mut self) -> usize {
         match self.take_while(|c| c.is_digit(10)) {
-            Some(end) => (idx0, I64(&self.text[idx0..end]), end),
-            None => (idx0, I64(&self.text[idx0..]), self.text.len()),
+            Some(end) => end,
+            None => self.text.len(),
         }
     }
 
@@ -340,6 +643,39 @@ impl <'input> Iterator for Tokenizer<'input> {
     }
 }
 
+// Decodes the escape sequences in the text of a string literal, which the tokenizer has already
+// checked are valid.
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                // Skip the '{' and read up to the '}'.
+                let digits: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+
+                if let Some(c) = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            }
+            // `\\`, `\"` and `\$` are just the character itself.
+            Some(c) => out.push(c),
+            None => (),
+        }
+    }
+
+    out
+}
+
 fn is_identifier_start(c: char) -> bool {
     UnicodeXID::is_xid_start(c)
 }